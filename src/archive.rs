@@ -0,0 +1,297 @@
+use crate::audio::{self, Recording};
+use crate::config::{ArchiveFormat, AudioConfig};
+use crate::runtime;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use hound::{WavSpec, WavWriter};
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+/// File types `prune` manages; anything else in the directory is left alone
+const RECORDING_EXTENSIONS: &[&str] = &["wav", "flac", "opus"];
+
+/// Opus bitrate for kept recordings (kbit/s); plenty for 16kHz speech
+const OPUS_BITRATE: u32 = 24;
+
+/// Kept recordings, pruned to the configured count, age and size limits
+///
+/// Recordings are written as WAV straight away; encoding and pruning happen
+/// one at a time on a background thread, so `flac` or `opusenc` never hold
+/// up the caller. Dropping the archive waits for the work still queued.
+pub struct Archive {
+    dir: PathBuf,
+    format: ArchiveFormat,
+    limits: Limits,
+    jobs: Option<Sender<PathBuf>>,
+    errors: Receiver<anyhow::Error>,
+    worker: Option<JoinHandle<()>>,
+}
+
+/// How much of the archive to keep
+#[derive(Debug, Clone)]
+struct Limits {
+    max_count: usize,
+    max_age: Option<Duration>,
+    max_bytes: Option<u64>,
+}
+
+impl Archive {
+    pub fn new(config: &AudioConfig) -> Self {
+        let dir = PathBuf::from(shellexpand::tilde(&config.archive_dir).to_string());
+        let format = config.archive_format;
+        let limits = Limits {
+            max_count: config.archive_max_count,
+            max_age: (config.archive_max_age_days > 0)
+                .then(|| Duration::from_secs(config.archive_max_age_days * 24 * 60 * 60)),
+            max_bytes: (config.archive_max_size_mb > 0)
+                .then(|| config.archive_max_size_mb * 1024 * 1024),
+        };
+
+        let (jobs, queue) = mpsc::channel::<PathBuf>();
+        let (report, errors) = mpsc::channel();
+        let worker = {
+            let dir = dir.clone();
+            let limits = limits.clone();
+            thread::spawn(move || {
+                for wav_path in queue {
+                    let kept = encode(&wav_path, format).and_then(|()| limits.apply(&dir));
+                    if let Err(e) = kept {
+                        let _ = report.send(e);
+                    }
+                }
+            })
+        };
+
+        Self {
+            dir,
+            format,
+            limits,
+            jobs: Some(jobs),
+            errors,
+            worker: Some(worker),
+        }
+    }
+
+    /// Save a recording under its history timestamp, then apply the limits
+    ///
+    /// Returns where the recording will be once encoded; failures after the
+    /// WAV is written are reported by [`Archive::take_error`].
+    pub fn save(&self, recording: &Recording, timestamp: &DateTime<Local>) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .context(format!("Failed to create recordings directory at {:?}", self.dir))?;
+
+        let name = timestamp.format("%Y%m%d-%H%M%S%.3f").to_string();
+        let wav_path = self.dir.join(format!("{}.wav", name));
+        audio::write_wav(&wav_path, &recording.samples, recording.sample_rate)?;
+
+        let path = self.dir.join(format!("{}.{}", name, self.format.extension()));
+        match self.jobs {
+            Some(ref jobs) if jobs.send(wav_path).is_ok() => {}
+            _ => anyhow::bail!("The archive worker has stopped"),
+        }
+        Ok(path)
+    }
+
+    /// A failure from the background encoding or pruning, if there was one
+    pub fn take_error(&self) -> Option<anyhow::Error> {
+        self.errors.try_recv().ok()
+    }
+
+    /// Delete the oldest recordings until every limit is met
+    pub fn prune(&self) -> Result<()> {
+        self.limits.apply(&self.dir)
+    }
+}
+
+impl Drop for Archive {
+    fn drop(&mut self) {
+        // Closing the queue lets the worker finish what is left and exit
+        self.jobs.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Encode a freshly written WAV into `format` next to it, dropping the WAV
+fn encode(wav_path: &Path, format: ArchiveFormat) -> Result<()> {
+    let path = wav_path.with_extension(format.extension());
+    let encoded = match format {
+        ArchiveFormat::Wav => return Ok(()),
+        ArchiveFormat::Flac => run_tool(
+            Command::new("flac")
+                .args(["--silent", "--force", "-o"])
+                .arg(&path)
+                .arg(wav_path),
+        ),
+        ArchiveFormat::Opus => run_tool(
+            Command::new("opusenc")
+                .args(["--quiet", "--bitrate", &OPUS_BITRATE.to_string()])
+                .arg(wav_path)
+                .arg(&path),
+        ),
+    };
+    let _ = fs::remove_file(wav_path);
+    match encoded {
+        Ok(()) => {
+            debug!(path = %path.display(), "Recording encoded");
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&path);
+            Err(e)
+        }
+    }
+}
+
+impl Limits {
+    /// Delete the oldest recordings in `dir` until every limit is met
+    fn apply(&self, dir: &Path) -> Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(format!("Failed to read {:?}", dir)),
+        };
+
+        // (modified, size, path), newest first
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let extension = path.extension()?.to_str()?;
+                if !RECORDING_EXTENSIONS.contains(&extension) {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                metadata.is_file().then(|| {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    (modified, metadata.len(), path)
+                })
+            })
+            .collect();
+        files.sort_by_key(|&(modified, _, _)| Reverse(modified));
+
+        let now = SystemTime::now();
+        let mut kept = 0;
+        let mut kept_bytes = 0;
+        for (modified, size, path) in files {
+            let too_old = self.max_age.is_some_and(|max_age| {
+                now.duration_since(modified).unwrap_or_default() > max_age
+            });
+            let too_many = self.max_count > 0 && kept >= self.max_count;
+            let too_big = self
+                .max_bytes
+                .is_some_and(|max_bytes| kept_bytes + size > max_bytes);
+
+            if too_old || too_many || too_big {
+                let _ = fs::remove_file(&path);
+            } else {
+                kept += 1;
+                kept_bytes += size;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run an encoder or decoder, failing on a non-zero exit
+fn run_tool(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .context(format!("Failed to run {} (is it installed?)", program))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Read a kept recording into mono samples and its sample rate
+pub fn load(path: &Path) -> Result<(Vec<f32>, u32)> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        // Compressed files are decoded by the tool that encoded them
+        Some("flac") => decode(|output| {
+            let mut command = Command::new("flac");
+            command
+                .args(["--silent", "--force", "--decode", "-o"])
+                .arg(output)
+                .arg(path);
+            command
+        }),
+        // At whisper's rate, since Opus always decodes to 48kHz otherwise
+        Some("opus") => decode(|output| {
+            let mut command = Command::new("opusdec");
+            command
+                .args(["--quiet", "--rate", "16000"])
+                .arg(path)
+                .arg(output);
+            command
+        }),
+        _ => audio::read_wav(path),
+    }
+}
+
+/// Run the decoder `command` builds for an output WAV, and read that WAV
+fn decode(command: impl FnOnce(&Path) -> Command) -> Result<(Vec<f32>, u32)> {
+    // A private file in the runtime directory, not next to the recording
+    let (wav_path, _) = runtime::create_file(&runtime::dir()?, "dec", "wav")?;
+    let decoded = run_tool(&mut command(&wav_path)).and_then(|()| audio::read_wav(&wav_path));
+    let _ = fs::remove_file(&wav_path);
+    decoded
+}
+
+/// Play a kept recording through ALSA's `aplay`
+///
+/// Any format is decoded first and streamed to `aplay` as WAV.
+pub fn play(path: &Path) -> Result<Child> {
+    if !path.exists() {
+        anyhow::bail!("Recording {:?} is no longer kept", path);
+    }
+
+    let (samples, sample_rate) = load(path)?;
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut wav = Cursor::new(Vec::new());
+    let mut writer = WavWriter::new(&mut wav, spec)?;
+    for &sample in &samples {
+        writer.write_sample(audio::to_i16(sample))?;
+    }
+    writer.finalize()?;
+
+    let mut child = Command::new("aplay")
+        .args(["-q", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run aplay")?;
+
+    // Feed aplay from a thread so the UI keeps running; stopping playback
+    // kills aplay, which ends the write
+    if let Some(mut stdin) = child.stdin.take() {
+        let wav = wav.into_inner();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&wav);
+        });
+    }
+
+    Ok(child)
+}
@@ -1,188 +1,353 @@
 use anyhow::{Context, Result};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Stream, StreamConfig};
+use crate::config::AudioConfig;
+use crate::dsp::DspChain;
+use crate::runtime;
+use crate::source::{AudioSource, CpalSource, SampleCallback};
+use crate::transcribe;
+use crate::vad::{Vad, VadEvent};
 use hound::{WavSpec, WavWriter};
+use rtrb::{Consumer, RingBuffer};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
-use std::time::Instant;
-
-/// Audio events sent from the capture thread
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Input samples at or beyond this level count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// First wait before reopening a lost device, doubled after each failure
+const RETRY_MIN: Duration = Duration::from_secs(1);
+/// Longest wait between attempts to reopen a lost device
+const RETRY_MAX: Duration = Duration::from_secs(30);
+/// How often to check whether the system default device has changed
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Audio the ring between the source and the processing thread holds (seconds)
+const RING_SECONDS: u32 = 2;
+/// Audio processed at a time (milliseconds); sources deliver whole multiples
+const PROCESS_MS: u32 = 10;
+/// How long the processing thread sleeps when the ring is empty
+const PROCESS_IDLE: Duration = Duration::from_millis(5);
+/// How long a source that can block waits for room in the ring
+const RING_FULL_WAIT: Duration = Duration::from_millis(1);
+/// Audio each level update covers (milliseconds)
+const LEVEL_MS: u32 = 50;
+/// Least time between warnings about dropped samples
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Audio events sent from the processing thread
 #[derive(Debug, Clone)]
 pub enum AudioEvent {
-    /// Audio level update (RMS value 0.0-1.0)
-    Level(f32),
+    /// Audio level update over the last 50ms (RMS and absolute sample peak, 0.0-1.0)
+    Level { rms: f32, peak: f32 },
     /// Voice activity detected
     VoiceDetected,
     /// Recording started
     RecordingStarted,
-    /// Recording stopped, file path provided
-    RecordingStopped(PathBuf),
+    /// Recording stopped, saved recording provided
+    RecordingStopped(Recording),
     /// Silence detected
     SilenceDetected,
     /// Error occurred
     Error(String),
+    /// The input device failed or went quiet; capture stopped until it is reopened
+    DeviceLost,
+    /// A lost input device was reopened, named
+    DeviceRestored(String),
+    /// Capture moved to the new system default device, named
+    DeviceChanged(String),
+}
+
+/// A finished recording ready for transcription
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Path to the saved WAV file, if the backend needs one
+    pub path: Option<PathBuf>,
+    /// Recorded mono samples
+    pub samples: Vec<f32>,
+    /// Sample rate of `samples`
+    pub sample_rate: u32,
+    /// Fraction of the speech portion above the silence threshold (0.0-1.0)
+    pub voiced_ratio: f32,
+    /// Highest absolute input sample (0.0-1.0)
+    pub peak: f32,
+    /// Input samples at or beyond full scale
+    pub clipped_samples: usize,
+    /// Silence the VAD waited out before stopping (seconds)
+    pub silence_wait: f32,
+    /// Time taken to save `path` (seconds)
+    pub wav_write: f32,
+}
+
+impl Recording {
+    /// A recording of existing audio, saved to `wav_dir` if the backend needs a file
+    ///
+    /// Used to transcribe audio that didn't come through the VAD, such as kept
+    /// recordings or benchmark files.
+    pub fn from_samples(samples: Vec<f32>, sample_rate: u32, wav_dir: Option<&Path>) -> Result<Self> {
+        let write_started = Instant::now();
+        let path = match wav_dir {
+            Some(dir) => Some(save_wav(dir, &samples, sample_rate)?),
+            None => None,
+        };
+
+        Ok(Self {
+            path,
+            samples,
+            sample_rate,
+            voiced_ratio: 1.0,
+            peak: 0.0,
+            clipped_samples: 0,
+            silence_wait: 0.0,
+            wav_write: write_started.elapsed().as_secs_f32(),
+        })
+    }
+
+    /// Length of the recording (seconds)
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Remove the recording's temporary WAV file, if it has one
+    pub fn discard(&self) {
+        if let Some(ref path) = self.path {
+            let _ = transcribe::cleanup_audio_file(path);
+        }
+    }
 }
 
 /// Audio capture and VAD state
 struct CaptureState {
-    /// Whether we're currently recording
-    recording: bool,
+    /// Decides when an utterance starts and ends
+    vad: Vad,
     /// Buffer for recorded samples
     buffer: Vec<f32>,
-    /// Time when silence was first detected
-    silence_start: Option<Instant>,
-    /// Time when recording started
-    recording_start: Option<Instant>,
-    /// Voice threshold
-    voice_threshold: f32,
-    /// Silence threshold
-    silence_threshold: f32,
-    /// Silence duration before stopping (seconds)
-    silence_duration: f32,
-    /// Minimum speech duration (seconds)
-    min_speech_duration: f32,
+    /// Highest absolute input sample of the recording
+    peak: f32,
+    /// Input samples of the recording at or beyond full scale
+    clipped_samples: usize,
     /// Sample rate
     sample_rate: u32,
+    /// Clean-up applied before the VAD
+    dsp: DspChain,
+    /// Record the unprocessed audio for whisper
+    raw_transcription: bool,
     /// Event sender
     event_tx: Sender<AudioEvent>,
+    /// Mirrors the VAD, so checking it never waits for processing
+    recording: Arc<AtomicBool>,
+    /// Sum of squared processed samples since the last level update
+    level_squares: f32,
+    /// Highest absolute input sample since the last level update
+    level_peak: f32,
+    /// Samples since the last level update
+    level_samples: usize,
+    /// Samples each level update covers
+    level_interval: usize,
 }
 
 impl CaptureState {
-    fn new(
-        voice_threshold: f32,
-        silence_threshold: f32,
-        silence_duration: f32,
-        min_speech_duration: f32,
-        sample_rate: u32,
-        event_tx: Sender<AudioEvent>,
-    ) -> Self {
+    fn new(config: &AudioConfig, event_tx: Sender<AudioEvent>, recording: Arc<AtomicBool>) -> Self {
         Self {
-            recording: false,
+            vad: Vad::new(config),
             buffer: Vec::new(),
-            silence_start: None,
-            recording_start: None,
-            voice_threshold,
-            silence_threshold,
-            silence_duration,
-            min_speech_duration,
-            sample_rate,
+            peak: 0.0,
+            clipped_samples: 0,
+            sample_rate: config.sample_rate,
+            dsp: DspChain::new(config),
+            raw_transcription: config.raw_transcription,
             event_tx,
+            recording,
+            level_squares: 0.0,
+            level_peak: 0.0,
+            level_samples: 0,
+            level_interval: (config.sample_rate * LEVEL_MS / 1000).max(1) as usize,
         }
     }
 
-    fn process_samples(&mut self, samples: &[f32]) {
+    /// Run a chunk through the DSP and VAD
+    ///
+    /// Returns the recording the chunk finished, not yet saved.
+    fn process_samples(&mut self, raw: &[f32]) -> Option<Recording> {
+        let processed = self.dsp.process(raw);
+        // What gets recorded for whisper
+        let samples = if self.raw_transcription { raw } else { &processed };
+
         // Calculate RMS
-        let rms = calculate_rms(samples);
-
-        // Send level update
-        let _ = self.event_tx.send(AudioEvent::Level(rms));
-
-        // State machine logic
-        if !self.recording {
-            // Not recording - check for voice activity
-            if rms > self.voice_threshold {
-                // Voice detected!
-                self.recording = true;
-                self.recording_start = Some(Instant::now());
-                self.silence_start = None;
-                self.buffer.clear();
-                self.buffer.extend_from_slice(samples);
+        let rms = calculate_rms(&processed);
+
+        // Clipping happens at the input, before any processing
+        let peak = raw.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let clipped = raw.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+
+        // Level updates at a steady rate, however the input is chunked
+        self.level_squares += processed.iter().map(|&s| s * s).sum::<f32>();
+        self.level_peak = self.level_peak.max(peak);
+        self.level_samples += processed.len();
+        if self.level_samples >= self.level_interval {
+            let _ = self.event_tx.send(AudioEvent::Level {
+                rms: (self.level_squares / self.level_samples as f32).sqrt(),
+                peak: self.level_peak,
+            });
+            self.level_squares = 0.0;
+            self.level_peak = 0.0;
+            self.level_samples = 0;
+        }
+
+        let recording = self.vad.is_active();
+        let event = self.vad.process(rms, samples.len());
+        if event == Some(VadEvent::SpeechStarted) {
+            self.buffer.clear();
+            self.peak = 0.0;
+            self.clipped_samples = 0;
+        }
+        if recording || event == Some(VadEvent::SpeechStarted) {
+            self.buffer.extend_from_slice(samples);
+            self.peak = self.peak.max(peak);
+            self.clipped_samples += clipped;
+        }
+
+        let mut finished = None;
+        match event {
+            Some(VadEvent::SpeechStarted) => {
                 let _ = self.event_tx.send(AudioEvent::VoiceDetected);
                 let _ = self.event_tx.send(AudioEvent::RecordingStarted);
             }
-        } else {
-            // Recording - add to buffer and check for silence
-            self.buffer.extend_from_slice(samples);
-
-            if rms < self.silence_threshold {
-                // Silence detected
-                if self.silence_start.is_none() {
-                    self.silence_start = Some(Instant::now());
-                    let _ = self.event_tx.send(AudioEvent::SilenceDetected);
-                } else if let Some(silence_start) = self.silence_start {
-                    // Check if silence duration exceeded
-                    let silence_elapsed = silence_start.elapsed().as_secs_f32();
-                    if silence_elapsed >= self.silence_duration {
-                        // Check minimum speech duration
-                        if let Some(recording_start) = self.recording_start {
-                            let recording_elapsed = recording_start.elapsed().as_secs_f32();
-                            if recording_elapsed >= self.min_speech_duration {
-                                // Stop recording and save
-                                self.stop_recording();
-                            } else {
-                                // Too short, cancel recording
-                                self.cancel_recording();
-                            }
-                        }
-                    }
-                }
-            } else {
-                // Voice still active, reset silence timer
-                self.silence_start = None;
+            Some(VadEvent::SilenceStarted) => {
+                let _ = self.event_tx.send(AudioEvent::SilenceDetected);
             }
+            Some(VadEvent::SpeechEnded) => finished = Some(self.stop_recording()),
+            // Too short, cancel recording
+            Some(VadEvent::TooShort) => self.cancel_recording(),
+            None => {}
         }
+        self.recording.store(self.vad.is_active(), Ordering::Relaxed);
+        finished
     }
 
-    fn stop_recording(&mut self) {
-        if !self.recording {
-            return;
-        }
+    /// Take the finished recording out, leaving the state ready for the next
+    fn stop_recording(&mut self) -> Recording {
+        let recording = Recording {
+            path: None,
+            voiced_ratio: self.vad.voiced_ratio(),
+            samples: std::mem::take(&mut self.buffer),
+            sample_rate: self.sample_rate,
+            peak: self.peak,
+            clipped_samples: self.clipped_samples,
+            silence_wait: self.vad.trailing_silence() as f32 / self.sample_rate as f32,
+            wav_write: 0.0,
+        };
+        self.cancel_recording();
+        recording
+    }
 
-        // Save to temporary WAV file
-        match self.save_wav() {
-            Ok(path) => {
-                let _ = self.event_tx.send(AudioEvent::RecordingStopped(path));
-            }
+    fn cancel_recording(&mut self) {
+        self.vad.reset();
+        self.buffer.clear();
+        self.recording.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Save a finished recording to `wav_dir`, if the transcriber reads from disk,
+/// and send it on
+fn finish_recording(mut recording: Recording, wav_dir: Option<&Path>, event_tx: &Sender<AudioEvent>) {
+    if let Some(dir) = wav_dir {
+        let write_started = Instant::now();
+        match save_wav(dir, &recording.samples, recording.sample_rate) {
+            Ok(path) => recording.path = Some(path),
             Err(e) => {
-                let _ = self.event_tx.send(AudioEvent::Error(format!("Failed to save audio: {}", e)));
+                let _ = event_tx.send(AudioEvent::Error(format!("Failed to save audio: {}", e)));
+                return;
             }
         }
+        recording.wav_write = write_started.elapsed().as_secs_f32();
+    }
+    let _ = event_tx.send(AudioEvent::RecordingStopped(recording));
+}
 
-        self.recording = false;
-        self.buffer.clear();
-        self.silence_start = None;
-        self.recording_start = None;
+/// Save samples to a new, uniquely named WAV file in `dir`
+///
+/// The file is removed again if writing it fails.
+pub fn save_wav(dir: &Path, samples: &[f32], sample_rate: u32) -> Result<PathBuf> {
+    let (path, file) = runtime::create_file(dir, "rec", "wav")?;
+
+    if let Err(e) = write_wav_file(file, samples, sample_rate) {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
     }
 
-    fn cancel_recording(&mut self) {
-        self.recording = false;
-        self.buffer.clear();
-        self.silence_start = None;
-        self.recording_start = None;
+    Ok(path)
+}
+
+/// Write mono f32 samples to a 16-bit PCM WAV file
+pub fn write_wav(path: &Path, samples: &[f32], sample_rate: u32) -> Result<()> {
+    let file = File::create(path)
+        .context(format!("Failed to create WAV file at {:?}", path))?;
+    write_wav_file(file, samples, sample_rate)
+}
+
+/// Write mono f32 samples as 16-bit PCM WAV to an open file
+fn write_wav_file(file: File, samples: &[f32], sample_rate: u32) -> Result<()> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut writer = WavWriter::new(BufWriter::new(file), spec)
+        .context("Failed to create WAV writer")?;
+
+    // Convert f32 samples to i16
+    for &sample in samples {
+        writer.write_sample(to_i16(sample))
+            .context("Failed to write sample")?;
     }
 
-    fn save_wav(&self) -> Result<PathBuf> {
-        // Create temporary file
-        let temp_dir = std::env::temp_dir();
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let path = temp_dir.join(format!("thehand_{}.wav", timestamp));
-
-        let spec = WavSpec {
-            channels: 1,
-            sample_rate: self.sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
+    writer.finalize()
+        .context("Failed to finalize WAV file")?;
 
-        let mut writer = WavWriter::create(&path, spec)
-            .context("Failed to create WAV writer")?;
+    Ok(())
+}
 
-        // Convert f32 samples to i16
-        for &sample in &self.buffer {
-            let sample_i16 = (sample * i16::MAX as f32) as i16;
-            writer.write_sample(sample_i16)
-                .context("Failed to write sample")?;
+/// Read a mono WAV file into f32 samples and its sample rate
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let mut reader = hound::WavReader::open(path)
+        .context(format!("Failed to open WAV file at {:?}", path))?;
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
         }
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+    };
+
+    // Keep the first channel of multi-channel files
+    let samples = samples
+        .into_iter()
+        .step_by(spec.channels.max(1) as usize)
+        .collect();
 
-        writer.finalize()
-            .context("Failed to finalize WAV file")?;
+    Ok((samples, spec.sample_rate))
+}
 
-        Ok(path)
+/// Convert an f32 sample to i16, saturating at full scale
+pub fn to_i16(sample: f32) -> i16 {
+    // NaN maps to silence rather than whatever the cast makes of it
+    if sample.is_nan() {
+        return 0;
     }
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
 }
 
 /// Calculate RMS (Root Mean Square) of audio samples
@@ -195,84 +360,304 @@ fn calculate_rms(samples: &[f32]) -> f32 {
     (sum / samples.len() as f32).sqrt()
 }
 
+/// Whether a restartable source is delivering
+enum Health {
+    Running,
+    /// Stopped after an error or stall, to be reopened at `retry_at`
+    Lost { retry_at: Instant, backoff: Duration },
+}
+
+/// Samples through the ring, counted by the callback and the processing thread
+#[derive(Default)]
+struct Flow {
+    /// Written to the ring by the source's callback
+    pushed: AtomicUsize,
+    /// Through the VAD, with their events sent
+    processed: AtomicUsize,
+    /// Lost because the ring was full
+    dropped: AtomicUsize,
+}
+
+/// Runs the DSP, VAD, WAV writing and events, away from the source's callback
+///
+/// The callback only copies samples into a lock-free ring; this thread drains
+/// it in `PROCESS_MS` chunks. A restarted source sends a new ring's consumer
+/// over `rings`. Finished recordings are saved to `wav_dir` outside the state
+/// lock, which the main thread takes too.
+fn spawn_processor(
+    state: Arc<Mutex<CaptureState>>,
+    wav_dir: Option<PathBuf>,
+    event_tx: Sender<AudioEvent>,
+    rings: Receiver<Consumer<f32>>,
+    flow: Arc<Flow>,
+    stop: Arc<AtomicBool>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    let chunk_len = (sample_rate * PROCESS_MS / 1000).max(1) as usize;
+    thread::spawn(move || {
+        let mut ring: Option<Consumer<f32>> = None;
+        let mut chunk = Vec::with_capacity(chunk_len);
+        let mut dropped = 0;
+        let mut warned_at: Option<Instant> = None;
+
+        while !stop.load(Ordering::Relaxed) {
+            // Whatever is left in an old ring came from a device since given up on
+            while let Ok(next) = rings.try_recv() {
+                ring = Some(next);
+            }
+
+            let total = flow.dropped.load(Ordering::Relaxed);
+            if total > dropped && warned_at.is_none_or(|at| at.elapsed() >= DROP_WARNING_INTERVAL) {
+                warn!(samples = total - dropped, "Audio processing fell behind; input dropped");
+                dropped = total;
+                warned_at = Some(Instant::now());
+            }
+
+            let read = ring
+                .as_mut()
+                .and_then(|ring| ring.read_chunk(ring.slots().min(chunk_len)).ok())
+                .filter(|read| !read.is_empty());
+            let Some(read) = read else {
+                thread::sleep(PROCESS_IDLE);
+                continue;
+            };
+
+            chunk.clear();
+            let (first, second) = read.as_slices();
+            chunk.extend_from_slice(first);
+            chunk.extend_from_slice(second);
+            read.commit_all();
+
+            let finished = state.lock().ok().and_then(|mut state| state.process_samples(&chunk));
+            if let Some(recording) = finished {
+                finish_recording(recording, wav_dir.as_deref(), &event_tx);
+            }
+            flow.processed.fetch_add(chunk.len(), Ordering::Release);
+        }
+    })
+}
+
 /// Audio capture manager
 pub struct AudioCapture {
-    #[allow(dead_code)]
-    stream: Stream,
+    /// Kept alive for as long as samples should keep coming
+    source: Box<dyn AudioSource>,
+    event_tx: Sender<AudioEvent>,
     event_rx: Receiver<AudioEvent>,
     state: Arc<Mutex<CaptureState>>,
+    /// Whether the VAD is recording, kept up by the processing thread
+    recording: Arc<AtomicBool>,
+    /// Hands the processing thread the ring of each (re)started source
+    ring_tx: Sender<Consumer<f32>>,
+    /// Samples of audio the ring holds
+    ring_capacity: usize,
+    flow: Arc<Flow>,
+    stop: Arc<AtomicBool>,
+    processor: Option<JoinHandle<()>>,
+    health: Health,
+    /// When the capture was set up; `last_delivery` counts from here
+    epoch: Instant,
+    /// Milliseconds after `epoch` that samples last arrived
+    last_delivery: Arc<AtomicU64>,
+    /// Reopen a device that delivers nothing for this long (None to never)
+    stall_timeout: Option<Duration>,
+    next_device_check: Instant,
 }
 
 impl AudioCapture {
-    /// Create a new audio capture instance
-    pub fn new(
-        voice_threshold: f32,
-        silence_threshold: f32,
-        silence_duration: f32,
-        min_speech_duration: f32,
-        sample_rate: u32,
+    /// Capture from the configured input device, or else the default one
+    pub fn new(config: &AudioConfig, wav_dir: Option<PathBuf>) -> Result<Self> {
+        let source = CpalSource::new(config.sample_rate).device(&config.device);
+        Self::with_source(Box::new(source), config, wav_dir)
+    }
+
+    /// Capture from any source delivering audio at `config.sample_rate`
+    pub fn with_source(
+        source: Box<dyn AudioSource>,
+        config: &AudioConfig,
+        wav_dir: Option<PathBuf>,
     ) -> Result<Self> {
+        if source.sample_rate() != config.sample_rate {
+            anyhow::bail!(
+                "Input is {} Hz but audio.sample_rate is {} Hz; resample it first",
+                source.sample_rate(),
+                config.sample_rate
+            );
+        }
+
         let (event_tx, event_rx) = channel();
+        let recording = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(Mutex::new(CaptureState::new(config, event_tx.clone(), recording.clone())));
+
+        let (ring_tx, ring_rx) = channel();
+        let flow = Arc::new(Flow::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let processor = spawn_processor(
+            state.clone(),
+            wav_dir,
+            event_tx.clone(),
+            ring_rx,
+            flow.clone(),
+            stop.clone(),
+            config.sample_rate,
+        );
+
+        let now = Instant::now();
+        let mut capture = Self {
+            source,
+            event_tx,
+            event_rx,
+            state,
+            recording,
+            ring_tx,
+            ring_capacity: (config.sample_rate * RING_SECONDS) as usize,
+            flow,
+            stop,
+            processor: Some(processor),
+            health: Health::Running,
+            epoch: now,
+            last_delivery: Arc::new(AtomicU64::new(0)),
+            stall_timeout: (config.stall_timeout > 0.0)
+                .then(|| Duration::from_secs_f32(config.stall_timeout)),
+            next_device_check: now + DEVICE_CHECK_INTERVAL,
+        };
 
-        let host = cpal::default_host();
-        let device = host.default_input_device()
-            .context("No input device available")?;
+        // A missing device may yet be plugged in; anything else can't recover
+        if let Err(e) = capture.start_source() {
+            if !capture.source.can_restart() {
+                return Err(e);
+            }
+            capture.lose(format!("{:#}", e));
+        }
 
-        let config = Self::get_config(&device, sample_rate)?;
+        Ok(capture)
+    }
 
-        let state = Arc::new(Mutex::new(CaptureState::new(
-            voice_threshold,
-            silence_threshold,
-            silence_duration,
-            min_speech_duration,
-            sample_rate,
-            event_tx.clone(),
-        )));
+    /// (Re)start the source, feeding the capture state
+    fn start_source(&mut self) -> Result<()> {
+        self.mark_delivery();
+        let callback = self.callback();
+        self.source.start(callback)
+    }
 
-        let stream = Self::build_stream(&device, &config, state.clone())?;
-        stream.play().context("Failed to start audio stream")?;
+    /// A callback feeding a new ring, whose other end goes to the processing thread
+    ///
+    /// It never locks or allocates, so it is safe on a realtime audio thread.
+    fn callback(&self) -> SampleCallback {
+        let (mut producer, consumer) = RingBuffer::new(self.ring_capacity);
+        let _ = self.ring_tx.send(consumer);
+
+        let capacity = self.ring_capacity;
+        let can_block = self.source.can_block();
+        let flow = self.flow.clone();
+        let last_delivery = self.last_delivery.clone();
+        let epoch = self.epoch;
+        Box::new(move |data: &[f32]| {
+            last_delivery.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+
+            // A file or pipe can wait for room, so none of it is dropped
+            if can_block {
+                while producer.slots() < data.len().min(capacity) && !producer.is_abandoned() {
+                    thread::sleep(RING_FULL_WAIT);
+                }
+            }
 
-        Ok(Self {
-            stream,
-            event_rx,
-            state,
+            let written = producer.slots().min(data.len());
+            if let Ok(chunk) = producer.write_chunk_uninit(written) {
+                chunk.fill_from_iter(data[..written].iter().copied());
+            }
+            flow.pushed.fetch_add(written, Ordering::Release);
+            if written < data.len() {
+                flow.dropped.fetch_add(data.len() - written, Ordering::Relaxed);
+            }
         })
     }
 
-    /// Get audio stream configuration
-    fn get_config(device: &Device, sample_rate: u32) -> Result<StreamConfig> {
-        let supported_config = device.default_input_config()
-            .context("Failed to get default input config")?;
+    fn mark_delivery(&self) {
+        self.last_delivery
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
 
-        Ok(StreamConfig {
-            channels: 1,
-            sample_rate: cpal::SampleRate(sample_rate),
-            buffer_size: cpal::BufferSize::Default,
-        })
+    /// Time since the source last delivered samples
+    fn since_delivery(&self) -> Duration {
+        let last = Duration::from_millis(self.last_delivery.load(Ordering::Relaxed));
+        self.epoch.elapsed().saturating_sub(last)
     }
 
-    /// Build audio input stream
-    fn build_stream(
-        device: &Device,
-        config: &StreamConfig,
-        state: Arc<Mutex<CaptureState>>,
-    ) -> Result<Stream> {
-        let err_fn = |err| {
-            eprintln!("Audio stream error: {}", err);
+    /// Stop a failed source and schedule reopening it
+    fn lose(&mut self, reason: String) {
+        self.source.stop();
+        self.cancel_recording();
+        let _ = self.event_tx.send(AudioEvent::Error(reason));
+        let _ = self.event_tx.send(AudioEvent::DeviceLost);
+        self.health = Health::Lost {
+            retry_at: Instant::now() + RETRY_MIN,
+            backoff: RETRY_MIN,
         };
+    }
 
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                if let Ok(mut state) = state.lock() {
-                    state.process_samples(data);
+    /// Watch a restartable source: reopen it after errors or stalls, and
+    /// follow the system default device when it changes
+    ///
+    /// Call regularly; results arrive as events.
+    pub fn supervise(&mut self) {
+        if !self.source.can_restart() {
+            if let Some(error) = self.source.take_error() {
+                let _ = self.event_tx.send(AudioEvent::Error(error));
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        match self.health {
+            Health::Running => {
+                if let Some(error) = self.source.take_error() {
+                    self.lose(error);
+                } else if let Some(timeout) =
+                    self.stall_timeout.filter(|&timeout| self.since_delivery() >= timeout)
+                {
+                    self.lose(format!(
+                        "No audio from the input device for {:.1}s",
+                        timeout.as_secs_f32()
+                    ));
+                } else if now >= self.next_device_check {
+                    self.next_device_check = now + DEVICE_CHECK_INTERVAL;
+                    if self.source.device_changed() {
+                        self.source.stop();
+                        self.cancel_recording();
+                        match self.start_source() {
+                            Ok(()) => {
+                                let name = self.source.device_name().unwrap_or_default();
+                                let _ = self.event_tx.send(AudioEvent::DeviceChanged(name));
+                            }
+                            Err(e) => self.lose(format!("{:#}", e)),
+                        }
+                    }
+                }
+            }
+            Health::Lost { retry_at, backoff } if now >= retry_at => match self.start_source() {
+                Ok(()) => {
+                    self.health = Health::Running;
+                    self.next_device_check = now + DEVICE_CHECK_INTERVAL;
+                    let name = self.source.device_name().unwrap_or_default();
+                    let _ = self.event_tx.send(AudioEvent::DeviceRestored(name));
+                }
+                Err(e) => {
+                    let backoff = (backoff * 2).min(RETRY_MAX);
+                    debug!(retry_in = backoff.as_secs(), "Failed to reopen input device: {:#}", e);
+                    self.health = Health::Lost {
+                        retry_at: now + backoff,
+                        backoff,
+                    };
                 }
             },
-            err_fn,
-            None,
-        ).context("Failed to build input stream")?;
+            Health::Lost { .. } => {}
+        }
+    }
 
-        Ok(stream)
+    /// Whether a file or pipe source has run out and all of it was processed
+    pub fn is_finished(&self) -> bool {
+        self.source.is_finished()
+            && self.flow.processed.load(Ordering::Acquire) == self.flow.pushed.load(Ordering::Acquire)
     }
 
     /// Get next audio event (non-blocking)
@@ -289,6 +674,58 @@ impl AudioCapture {
 
     /// Check if currently recording
     pub fn is_recording(&self) -> bool {
-        self.state.lock().map(|s| s.recording).unwrap_or(false)
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// Copy of the recording in progress from sample `from` onwards
+    pub fn snapshot(&self, from: usize) -> Option<Vec<f32>> {
+        let state = self.state.lock().ok()?;
+        if !state.vad.is_active() {
+            return None;
+        }
+        let from = from.min(state.buffer.len());
+        Some(state.buffer[from..].to_vec())
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        // Dropping the ring's consumer also frees a source waiting for room
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(processor) = self.processor.take() {
+            let _ = processor.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::source::{Signal, SyntheticSource};
+
+    #[test]
+    fn blocking_source_faster_than_real_time_loses_nothing() {
+        let config = Config::default().audio;
+        // Several rings' worth, delivered as fast as the ring takes it
+        let signals = [
+            Signal::Speech { amplitude: 0.3, seconds: 5.0 },
+            Signal::Silence(2.0),
+            Signal::Noise { amplitude: 0.01, seconds: 5.0 },
+        ];
+        let source = SyntheticSource::new(config.sample_rate, &signals);
+        let total = (12 * config.sample_rate) as usize;
+        let capture = AudioCapture::with_source(Box::new(source), &config, None).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !capture.is_finished() {
+            assert!(Instant::now() < deadline, "source never finished");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let flow = &capture.flow;
+        assert_eq!(flow.dropped.load(Ordering::Relaxed), 0);
+        assert_eq!(flow.pushed.load(Ordering::Acquire), total);
+        assert_eq!(flow.processed.load(Ordering::Acquire), total);
     }
 }
@@ -0,0 +1,192 @@
+use thehand::config::Config;
+use thehand::history::ExportFormat;
+use thehand::source::{AudioSource, FileSource, PcmFormat, PcmSource};
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Voice-activated transcription that types directly into your focused window
+#[derive(Debug, Parser)]
+#[command(name = "thehand", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub whisper: WhisperArgs,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Log more detail (-v for debug, -vv for trace)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Search and export the transcription history
+    History(HistoryArgs),
+    /// Transcribe kept recordings again and compare with the original text
+    ///
+    /// Combine with --model, --language and the other whisper options to
+    /// compare settings on your own voice.
+    Retranscribe(RetranscribeArgs),
+    /// Time transcription of a directory of recordings with one or more models
+    ///
+    /// Reports the WAV write, whisper wall time, real-time factor and
+    /// post-processing for each file, and averages per model.
+    Bench(BenchArgs),
+    /// Cancel the recording or transcription in progress in the running TheHand
+    ///
+    /// The same as pressing C in its window, for binding to a global hotkey.
+    Cancel,
+}
+
+/// Arguments for `thehand retranscribe`
+#[derive(Debug, Args)]
+pub struct RetranscribeArgs {
+    /// Only re-run transcriptions containing this text (case-insensitive)
+    pub search: Option<String>,
+
+    /// Re-run this many of the most recent kept recordings
+    #[arg(short = 'n', long, default_value_t = 1)]
+    pub limit: usize,
+}
+
+/// Arguments for `thehand bench`
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Directory of WAV, FLAC or Opus recordings
+    pub dir: PathBuf,
+
+    /// GGML model files to compare (defaults to the configured model)
+    #[arg(value_name = "MODEL")]
+    pub models: Vec<String>,
+}
+
+/// Arguments for `thehand history`
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Only show transcriptions containing this text (case-insensitive)
+    pub search: Option<String>,
+
+    /// Only show transcriptions from this day onwards (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<NaiveDate>,
+
+    /// Only show transcriptions up to and including this day (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub until: Option<NaiveDate>,
+
+    /// Show at most this many of the most recent matches
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+
+    /// Include transcriptions the hallucination filter rejected
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: ExportFormat,
+
+    /// Write to a file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/// Where to take audio from instead of the microphone
+#[derive(Debug, Args)]
+pub struct InputArgs {
+    /// Read a WAV, FLAC or Opus file, or raw PCM from a FIFO or "-" (stdin)
+    #[arg(short, long, value_name = "PATH")]
+    pub input: Option<PathBuf>,
+
+    /// Sample format of raw PCM input (mono, at audio.sample_rate)
+    #[arg(long, value_enum, default_value_t)]
+    pub input_format: PcmFormat,
+
+    /// Play input files as fast as possible instead of in real time
+    #[arg(long)]
+    pub fast: bool,
+}
+
+impl InputArgs {
+    /// The requested source, or `None` for the default input device
+    pub fn source(&self, sample_rate: u32) -> Result<Option<Box<dyn AudioSource>>> {
+        let Some(ref path) = self.input else { return Ok(None) };
+        if path.as_os_str() == "-" {
+            return Ok(Some(Box::new(PcmSource::stdin(self.input_format, sample_rate))));
+        }
+
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let source: Box<dyn AudioSource> = match extension {
+            Some("wav" | "flac" | "opus") => Box::new(FileSource::open(path, !self.fast)?),
+            _ => Box::new(PcmSource::open(path, self.input_format, sample_rate)?),
+        };
+        Ok(Some(source))
+    }
+}
+
+/// Overrides for the `[whisper]` config section
+#[derive(Debug, Args)]
+pub struct WhisperArgs {
+    /// Path to a GGML model file
+    #[arg(short, long, value_name = "FILE", global = true)]
+    pub model: Option<String>,
+
+    /// Spoken language code ("en", "de", ...), or "auto" to detect
+    #[arg(short, long, global = true)]
+    pub language: Option<String>,
+
+    /// Translate the transcription to English
+    #[arg(long, global = true)]
+    pub translate: bool,
+
+    /// Initial prompt to bias whisper toward domain vocabulary
+    #[arg(long, value_name = "TEXT", global = true)]
+    pub prompt: Option<String>,
+
+    /// Number of threads for inference
+    #[arg(short, long, global = true)]
+    pub threads: Option<u32>,
+
+    /// Beam search width
+    #[arg(long, global = true)]
+    pub beam_size: Option<u32>,
+
+    /// Sampling temperature (0.0-1.0)
+    #[arg(long, global = true)]
+    pub temperature: Option<f32>,
+}
+
+impl WhisperArgs {
+    /// Apply command-line overrides on top of the loaded config
+    pub fn apply(&self, config: &mut Config) {
+        let whisper = &mut config.whisper;
+
+        if let Some(ref model) = self.model {
+            whisper.model_path = shellexpand::tilde(model).to_string();
+        }
+        if let Some(ref language) = self.language {
+            whisper.language = language.clone();
+        }
+        if self.translate {
+            whisper.translate = true;
+        }
+        if self.prompt.is_some() {
+            whisper.initial_prompt = self.prompt.clone();
+        }
+        if self.threads.is_some() {
+            whisper.threads = self.threads;
+        }
+        if self.beam_size.is_some() {
+            whisper.beam_size = self.beam_size;
+        }
+        if self.temperature.is_some() {
+            whisper.temperature = self.temperature;
+        }
+    }
+}
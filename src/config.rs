@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -9,18 +11,131 @@ pub struct Config {
     pub audio: AudioConfig,
     pub ui: UiConfig,
     pub typing: TypingConfig,
+    /// Whisper hallucination filter
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Live partial transcriptions while recording
+    #[serde(default)]
+    pub streaming: StreamingConfig,
+    /// Persistent transcription history
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Ordered text post-processing steps applied to each transcription
+    #[serde(default)]
+    pub postprocess: Vec<PostProcessStep>,
+    /// HTTP endpoint exporting counters and latency histograms
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Diagnostic log, kept apart from the transcript log
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Only act on speech that starts with a wake phrase
+    #[serde(default)]
+    pub wake: WakeConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhisperConfig {
+    /// Transcription backend ("cli" or "whisper-rs")
+    #[serde(default)]
+    pub backend: TranscriptionBackend,
     /// Path to whisper.cpp binary
     pub binary_path: String,
     /// Path to GGML model file
     pub model_path: String,
+    /// Spoken language code ("en", "de", ...), or "auto" to detect
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Translate the transcription to English
+    #[serde(default)]
+    pub translate: bool,
+    /// Initial prompt to bias whisper toward domain vocabulary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// Number of threads for inference (whisper's default when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+    /// Beam search width (greedy decoding when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam_size: Option<u32>,
+    /// Sampling temperature (0.0-1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Base transcription timeout (seconds)
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: f32,
+    /// Extra timeout per second of recorded audio (seconds)
+    #[serde(default = "default_timeout_scale")]
+    pub timeout_scale: f32,
+}
+
+/// How recordings are transcribed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscriptionBackend {
+    /// Spawn the whisper.cpp binary per utterance
+    #[default]
+    Cli,
+    /// Load the model once and run it in-process (needs the whisper-rs feature)
+    WhisperRs,
+}
+
+impl TranscriptionBackend {
+    /// Whether recordings must be written to WAV files for this backend
+    pub fn needs_wav(&self) -> bool {
+        *self == TranscriptionBackend::Cli
+    }
+}
+
+/// How kept recordings are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    /// 16-bit PCM WAV
+    #[default]
+    Wav,
+    /// Lossless FLAC (needs the `flac` tool)
+    Flac,
+    /// Ogg/Opus, much smaller (needs `opusenc` and `opusdec`)
+    Opus,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Wav => "wav",
+            ArchiveFormat::Flac => "flac",
+            ArchiveFormat::Opus => "opus",
+        }
+    }
+
+    /// Programs that encode and decode this format
+    pub fn tools(&self) -> &'static [&'static str] {
+        match self {
+            ArchiveFormat::Wav => &[],
+            ArchiveFormat::Flac => &["flac"],
+            ArchiveFormat::Opus => &["opusenc", "opusdec"],
+        }
+    }
+}
+
+fn default_language() -> String {
+    "auto".to_string()
+}
+
+fn default_timeout_secs() -> f32 {
+    10.0
+}
+
+fn default_timeout_scale() -> f32 {
+    3.0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioConfig {
+    /// Input device to use (part of its name); empty to follow the system default
+    #[serde(default)]
+    pub device: String,
     /// Sample rate for recording (16kHz is whisper standard)
     pub sample_rate: u32,
     /// RMS threshold for voice detection (0.0-1.0)
@@ -31,6 +146,73 @@ pub struct AudioConfig {
     pub silence_duration: f32,
     /// Minimum speech duration to avoid false triggers (seconds)
     pub min_speech_duration: f32,
+    /// High-pass filter out DC offset, hum and desk thumps
+    #[serde(default)]
+    pub highpass: bool,
+    /// High-pass cutoff frequency (Hz)
+    #[serde(default = "default_highpass_hz")]
+    pub highpass_hz: f32,
+    /// Suppress steady background noise such as fans
+    #[serde(default)]
+    pub denoise: bool,
+    /// Automatic gain control toward `agc_target`
+    #[serde(default)]
+    pub agc: bool,
+    /// RMS level the AGC aims for (0.0-1.0)
+    #[serde(default = "default_agc_target")]
+    pub agc_target: f32,
+    /// Send unprocessed audio to whisper; the VAD still uses the processed audio
+    #[serde(default)]
+    pub raw_transcription: bool,
+    /// Keep each recording next to its history entry
+    #[serde(default)]
+    pub keep_recordings: bool,
+    /// Where kept recordings are stored
+    #[serde(default = "default_archive_dir")]
+    pub archive_dir: String,
+    /// Encoding of kept recordings ("wav", "flac" or "opus")
+    #[serde(default)]
+    pub archive_format: ArchiveFormat,
+    /// Most recordings to keep (0 for no limit)
+    #[serde(default = "default_archive_max_count")]
+    pub archive_max_count: usize,
+    /// Delete kept recordings older than this (days, 0 for no limit)
+    #[serde(default = "default_archive_max_age_days")]
+    pub archive_max_age_days: u64,
+    /// Most disk space kept recordings may use (MB, 0 for no limit)
+    #[serde(default = "default_archive_max_size_mb")]
+    pub archive_max_size_mb: u64,
+    /// Reopen the input device after this long without audio (seconds, 0 to never)
+    #[serde(default = "default_stall_timeout")]
+    pub stall_timeout: f32,
+}
+
+fn default_highpass_hz() -> f32 {
+    80.0
+}
+
+fn default_agc_target() -> f32 {
+    0.1
+}
+
+fn default_archive_dir() -> String {
+    "~/.local/share/thehand/recordings".to_string()
+}
+
+fn default_archive_max_count() -> usize {
+    500
+}
+
+fn default_archive_max_age_days() -> u64 {
+    30
+}
+
+fn default_stall_timeout() -> f32 {
+    5.0
+}
+
+fn default_archive_max_size_mb() -> u64 {
+    500
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,19 +231,233 @@ pub struct TypingConfig {
     pub keystroke_delay: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    /// Drop hallucinated or non-speech transcriptions instead of typing them
+    pub enabled: bool,
+    /// Remove "[BLANK_AUDIO]", "(music)" and similar annotations
+    pub strip_annotations: bool,
+    /// Transcriptions that are dropped when they are the whole output
+    pub hallucination_phrases: Vec<String>,
+    /// Reject output whose repeated word-pair fraction exceeds this (0.0-1.0)
+    pub max_repetition_ratio: f32,
+    /// Reject recordings voiced for less than this fraction (0.0-1.0)
+    pub min_voiced_ratio: f32,
+    /// Reject transcriptions whose mean token log probability is below this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_avg_logprob: Option<f32>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strip_annotations: true,
+            hallucination_phrases: [
+                "Thank you for watching.",
+                "Thanks for watching!",
+                "Please subscribe.",
+                "Like and subscribe.",
+                "Subtitles by the Amara.org community",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            max_repetition_ratio: 0.5,
+            min_voiced_ratio: 0.2,
+            min_avg_logprob: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamingConfig {
+    /// Re-transcribe the growing recording while you speak
+    pub enabled: bool,
+    /// Time between partial transcriptions (seconds)
+    pub interval: f32,
+    /// Longest stretch of audio transcribed per pass (seconds)
+    pub window: f32,
+    /// Type committed words as they become stable instead of at the end
+    pub type_incrementally: bool,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 1.0,
+            window: 15.0,
+            type_incrementally: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Save every transcription to the history file
+    pub enabled: bool,
+    /// History file location (one JSON record per line)
+    pub path: String,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "~/.local/share/thehand/history.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Serve Prometheus/OpenMetrics and JSON metrics over HTTP
+    pub enabled: bool,
+    /// Address to listen on; keep it on localhost, there is no authentication
+    pub address: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:9464".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WakeConfig {
+    /// Ignore utterances that don't start with the wake phrase
+    pub enabled: bool,
+    /// Words that must open an utterance; stripped from the typed text
+    pub phrase: String,
+    /// Model for the wake phrase check (empty to use whisper.model_path)
+    pub model_path: String,
+    /// How much of the start of each utterance the check hears (seconds)
+    pub listen_seconds: f32,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            phrase: "hey hand".to_string(),
+            model_path: String::new(),
+            listen_seconds: 2.0,
+        }
+    }
+}
+
+/// Least severe diagnostic messages written to the log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// The next more verbose level, if there is one
+    pub fn more_verbose(self) -> Self {
+        match self {
+            LogLevel::Error => LogLevel::Warn,
+            LogLevel::Warn => LogLevel::Info,
+            LogLevel::Info => LogLevel::Debug,
+            LogLevel::Debug | LogLevel::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Least severe messages to keep; `--verbose` raises it
+    pub level: LogLevel,
+    /// Log directory; empty for `$XDG_STATE_HOME/thehand`
+    pub dir: String,
+    /// Daily log files to keep before the oldest is deleted
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            dir: String::new(),
+            max_files: 7,
+        }
+    }
+}
+
+/// A single step in the `[[postprocess]]` pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PostProcessStep {
+    /// Replace every occurrence of `from` with `to`
+    Replace { from: String, to: String },
+    /// Regex replacement (`$1` style capture references allowed)
+    Regex { pattern: String, replacement: String },
+    /// Case-insensitive whole-word corrections (heard phrase -> spelling)
+    Dictionary { words: BTreeMap<String, String> },
+    /// Drop a single trailing period
+    StripTrailingPeriod,
+    /// Lowercase the first letter
+    LowercaseFirst,
+    /// Collapse runs of whitespace and trim
+    CollapseWhitespace,
+    /// Append text such as a space or newline
+    Append { text: String },
+    /// Convert spelled-out numbers to digits
+    NumberWords,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             whisper: WhisperConfig {
+                backend: TranscriptionBackend::Cli,
                 binary_path: "/usr/local/bin/whisper".to_string(),
                 model_path: "~/.local/share/thehand/models/ggml-base.bin".to_string(),
+                language: default_language(),
+                translate: false,
+                initial_prompt: None,
+                threads: None,
+                beam_size: None,
+                temperature: None,
+                timeout_secs: default_timeout_secs(),
+                timeout_scale: default_timeout_scale(),
             },
             audio: AudioConfig {
+                device: String::new(),
                 sample_rate: 16000,
                 voice_threshold: 0.02,
                 silence_threshold: 0.01,
                 silence_duration: 2.0,
                 min_speech_duration: 0.5,
+                highpass: false,
+                highpass_hz: default_highpass_hz(),
+                denoise: false,
+                agc: false,
+                agc_target: default_agc_target(),
+                raw_transcription: false,
+                keep_recordings: false,
+                archive_dir: default_archive_dir(),
+                archive_format: ArchiveFormat::Wav,
+                archive_max_count: default_archive_max_count(),
+                archive_max_age_days: default_archive_max_age_days(),
+                archive_max_size_mb: default_archive_max_size_mb(),
+                stall_timeout: default_stall_timeout(),
             },
             ui: UiConfig {
                 history_limit: 50,
@@ -71,12 +467,22 @@ impl Default for Config {
             typing: TypingConfig {
                 keystroke_delay: 10,
             },
+            filter: FilterConfig::default(),
+            streaming: StreamingConfig::default(),
+            history: HistoryConfig::default(),
+            postprocess: Vec::new(),
+            metrics: MetricsConfig::default(),
+            logging: LoggingConfig::default(),
+            wake: WakeConfig::default(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from default path or create default config
+    ///
+    /// The result is not validated, so that commands which never run whisper
+    /// still work; call [`Config::validate`] before transcribing.
     pub fn load() -> Result<Self> {
         let config_path = Self::config_path()?;
 
@@ -98,8 +504,10 @@ impl Config {
         config.whisper.binary_path = Self::expand_path(&config.whisper.binary_path);
         config.whisper.model_path = Self::expand_path(&config.whisper.model_path);
         config.ui.log_path = Self::expand_path(&config.ui.log_path);
-
-        config.validate()?;
+        config.history.path = Self::expand_path(&config.history.path);
+        config.audio.archive_dir = Self::expand_path(&config.audio.archive_dir);
+        config.logging.dir = Self::expand_path(&config.logging.dir);
+        config.wake.model_path = Self::expand_path(&config.wake.model_path);
 
         Ok(config)
     }
@@ -128,7 +536,7 @@ impl Config {
     pub fn validate(&self) -> Result<()> {
         // Check if whisper binary exists
         let whisper_path = PathBuf::from(&self.whisper.binary_path);
-        if !whisper_path.exists() {
+        if self.whisper.backend == TranscriptionBackend::Cli && !whisper_path.exists() {
             anyhow::bail!(
                 "Whisper binary not found at {:?}\n\
                 Please install whisper.cpp and update the binary_path in your config.",
@@ -146,6 +554,37 @@ impl Config {
             );
         }
 
+        if self.whisper.backend == TranscriptionBackend::WhisperRs {
+            if !cfg!(feature = "whisper-rs") {
+                anyhow::bail!(
+                    "whisper.backend = \"whisper-rs\" needs TheHand built with --features whisper-rs"
+                );
+            }
+            if self.audio.sample_rate != 16000 {
+                anyhow::bail!("The whisper-rs backend needs audio.sample_rate = 16000");
+            }
+        }
+
+        // Validate whisper options
+        if self.whisper.language.trim().is_empty() {
+            anyhow::bail!("whisper.language must be a language code or \"auto\"");
+        }
+        if self.whisper.threads == Some(0) {
+            anyhow::bail!("whisper.threads must be at least 1");
+        }
+        if self.whisper.beam_size == Some(0) {
+            anyhow::bail!("whisper.beam_size must be at least 1");
+        }
+        if let Some(temperature) = self.whisper.temperature {
+            if !(0.0..=1.0).contains(&temperature) {
+                anyhow::bail!("whisper.temperature must be between 0.0 and 1.0");
+            }
+        }
+
+        if self.whisper.timeout_secs <= 0.0 || self.whisper.timeout_scale < 0.0 {
+            anyhow::bail!("whisper.timeout_secs must be positive and timeout_scale non-negative");
+        }
+
         // Validate thresholds
         if self.audio.voice_threshold <= 0.0 || self.audio.voice_threshold > 1.0 {
             anyhow::bail!("voice_threshold must be between 0.0 and 1.0");
@@ -156,6 +595,82 @@ impl Config {
         if self.audio.silence_threshold >= self.audio.voice_threshold {
             anyhow::bail!("silence_threshold must be less than voice_threshold");
         }
+        let nyquist = self.audio.sample_rate as f32 / 2.0;
+        if self.audio.highpass && (self.audio.highpass_hz <= 0.0 || self.audio.highpass_hz >= nyquist) {
+            anyhow::bail!("highpass_hz must be between 0 and {} Hz", nyquist);
+        }
+        if self.audio.stall_timeout < 0.0 {
+            anyhow::bail!("audio.stall_timeout must be 0 or positive");
+        }
+        if self.audio.agc_target <= 0.0 || self.audio.agc_target > 1.0 {
+            anyhow::bail!("agc_target must be between 0.0 and 1.0");
+        }
+
+        if !(0.0..=1.0).contains(&self.filter.max_repetition_ratio) {
+            anyhow::bail!("filter.max_repetition_ratio must be between 0.0 and 1.0");
+        }
+        if !(0.0..=1.0).contains(&self.filter.min_voiced_ratio) {
+            anyhow::bail!("filter.min_voiced_ratio must be between 0.0 and 1.0");
+        }
+
+        if let Some(min_avg_logprob) = self.filter.min_avg_logprob {
+            if min_avg_logprob > 0.0 {
+                anyhow::bail!("filter.min_avg_logprob must be 0.0 or negative");
+            }
+        }
+
+        if self.streaming.interval <= 0.0 {
+            anyhow::bail!("streaming.interval must be positive");
+        }
+        if self.streaming.window <= self.streaming.interval || self.streaming.window > 30.0 {
+            anyhow::bail!("streaming.window must be longer than the interval and at most 30 seconds");
+        }
+        // Both judge or rewrite whole utterances, which words typed live never wait for
+        if self.streaming.enabled && self.streaming.type_incrementally {
+            if self.filter.enabled {
+                anyhow::bail!("streaming.type_incrementally needs filter.enabled = false");
+            }
+            if !self.postprocess.is_empty() {
+                anyhow::bail!("streaming.type_incrementally can't be combined with postprocess steps");
+            }
+        }
+
+        if self.audio.keep_recordings {
+            if let Some(tool) = self.audio.archive_format.tools().iter().find(|tool| !on_path(tool)) {
+                anyhow::bail!(
+                    "archive_format = \"{}\" needs the {} program, which isn't on your PATH",
+                    self.audio.archive_format.extension(),
+                    tool
+                );
+            }
+        }
+
+        if self.metrics.enabled && self.metrics.address.parse::<SocketAddr>().is_err() {
+            anyhow::bail!("metrics.address must be an IP address and port, like 127.0.0.1:9464");
+        }
+
+        if self.logging.max_files == 0 {
+            anyhow::bail!("logging.max_files must be at least 1");
+        }
+
+        if self.wake.enabled {
+            if self.wake.phrase.split_whitespace().next().is_none() {
+                anyhow::bail!("wake.phrase must have at least one word");
+            }
+            if !self.wake.model_path.is_empty() && !Path::new(&self.wake.model_path).exists() {
+                anyhow::bail!("Wake phrase model not found at {:?}", self.wake.model_path);
+            }
+            if self.wake.listen_seconds <= 0.0 {
+                anyhow::bail!("wake.listen_seconds must be positive");
+            }
+            // Words typed live would go out before the wake phrase was checked
+            if self.streaming.enabled && self.streaming.type_incrementally {
+                anyhow::bail!("wake.enabled can't be combined with streaming.type_incrementally");
+            }
+        }
+
+        // Make sure the post-processing pipeline compiles
+        crate::postprocess::PostProcessor::new(&self.postprocess)?;
 
         Ok(())
     }
@@ -172,3 +687,10 @@ impl Config {
         shellexpand::tilde(path).to_string()
     }
 }
+
+/// Whether `program` can be found in a directory on `$PATH`
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| dir.join(program).is_file())
+    })
+}
@@ -0,0 +1,154 @@
+use crate::runtime;
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// How often the listener checks whether it should stop
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a client gets to send its command and read the reply
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest command line read; real commands are a single word
+const MAX_REQUEST: u64 = 256;
+
+/// A request from another process, such as `thehand cancel` bound to a hotkey
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Cancel the recording or transcription in progress
+    Cancel,
+}
+
+impl ControlCommand {
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "cancel" => Some(Self::Cancel),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cancel => "cancel",
+        }
+    }
+}
+
+/// Default socket, inside the private runtime directory
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(runtime::dir()?.join("control.sock"))
+}
+
+/// Unix socket taking one command per connection, served in a background
+/// thread and polled without blocking
+///
+/// The socket file is removed when dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+    commands: Receiver<ControlCommand>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlSocket {
+    /// Listen on `path`, replacing a socket left behind by a crashed process
+    pub fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("Another TheHand is already listening on {:?}", path);
+            }
+            std::fs::remove_file(path)
+                .context(format!("Failed to remove stale control socket {:?}", path))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .context(format!("Failed to listen for commands on {:?}", path))?;
+        listener.set_nonblocking(true)?;
+        info!(path = %path.display(), "Listening for commands");
+
+        let (tx, commands) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        // A client that misbehaves only loses its own reply
+                        Ok((stream, _)) => match serve(stream) {
+                            Ok(command) => {
+                                if let Some(command) = command {
+                                    let _ = tx.send(command);
+                                }
+                            }
+                            Err(e) => debug!("Control request failed: {:#}", e),
+                        },
+                        // Nothing waiting (or a transient accept error)
+                        Err(_) => thread::sleep(ACCEPT_INTERVAL),
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            commands,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Commands received since the last poll
+    pub fn poll(&self) -> Vec<ControlCommand> {
+        self.commands.try_iter().collect()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Read one command and acknowledge it
+fn serve(stream: UnixStream) -> Result<Option<ControlCommand>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).take(MAX_REQUEST).read_line(&mut line)?;
+    let command = ControlCommand::parse(&line);
+    let reply = match command {
+        Some(_) => "ok\n".to_string(),
+        None => format!("error: unknown command {:?}\n", line.trim()),
+    };
+    (&stream).write_all(reply.as_bytes())?;
+    Ok(command)
+}
+
+/// Send `command` to the running TheHand listening on `path`
+pub fn send(path: &Path, command: ControlCommand) -> Result<()> {
+    let mut stream = UnixStream::connect(path)
+        .context(format!("TheHand is not running (no control socket at {:?})", path))?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    writeln!(stream, "{}", command.as_str())?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .context("No reply from TheHand")?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        other => anyhow::bail!("TheHand refused {:?}: {}", command.as_str(), other),
+    }
+}
@@ -0,0 +1,250 @@
+use crate::config::AudioConfig;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Optional clean-up applied to captured audio before the VAD sees it
+///
+/// Stages run in order: high-pass, denoiser, AGC. Each is skipped when
+/// disabled in `[audio]`.
+pub struct DspChain {
+    highpass: Option<HighPass>,
+    denoiser: Option<Denoiser>,
+    agc: Option<Agc>,
+}
+
+impl DspChain {
+    pub fn new(config: &AudioConfig) -> Self {
+        Self {
+            highpass: config
+                .highpass
+                .then(|| HighPass::new(config.highpass_hz, config.sample_rate)),
+            denoiser: config.denoise.then(Denoiser::new),
+            agc: config.agc.then(|| Agc::new(config.agc_target)),
+        }
+    }
+
+    /// Process a chunk of samples, returning the same number of samples
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        if let Some(ref mut highpass) = self.highpass {
+            highpass.process(&mut samples);
+        }
+        if let Some(ref mut denoiser) = self.denoiser {
+            samples = denoiser.process(&samples);
+        }
+        if let Some(ref mut agc) = self.agc {
+            agc.process(&mut samples);
+        }
+        samples
+    }
+}
+
+/// Second-order Butterworth high-pass; removes DC offset, hum and desk thumps
+struct HighPass {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    /// Transposed direct form II state
+    z1: f32,
+    z2: f32,
+}
+
+impl HighPass {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        // RBJ audio EQ cookbook, Q = 1/sqrt(2)
+        let w0 = 2.0 * PI * cutoff / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let x = *sample;
+            let y = self.b0 * x + self.z1;
+            self.z1 = self.b1 * x - self.a1 * y + self.z2;
+            self.z2 = self.b2 * x - self.a2 * y;
+            *sample = y;
+        }
+    }
+}
+
+/// FFT frame length for the denoiser (32ms at 16kHz)
+const FRAME: usize = 512;
+/// Frames overlap by half
+const HOP: usize = FRAME / 2;
+/// Frames averaged for the initial noise estimate
+const NOISE_LEARN_FRAMES: usize = 10;
+/// How much of the noise estimate is subtracted from each bin
+const OVER_SUBTRACTION: f32 = 2.0;
+/// Lowest gain a bin can get, to avoid "musical noise"
+const SPECTRAL_FLOOR: f32 = 0.1;
+
+/// Spectral subtraction against a slowly tracked noise spectrum
+///
+/// Overlap-add with a square-root Hann window on both analysis and synthesis,
+/// which delays the audio by `FRAME` samples. The noise spectrum is learned
+/// from the first frames, so start it in a quiet room.
+struct Denoiser {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    window: Vec<f32>,
+    /// Last `FRAME` input samples
+    frame: Vec<f32>,
+    /// Input not yet making up a full hop
+    pending: Vec<f32>,
+    /// Processed samples ready to hand out
+    output: VecDeque<f32>,
+    /// Second half of the previous frame, waiting to be overlapped
+    overlap: Vec<f32>,
+    /// Estimated noise magnitude per bin
+    noise: Vec<f32>,
+    frames_seen: usize,
+}
+
+impl Denoiser {
+    fn new() -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let window = (0..FRAME)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME as f32).cos()).sqrt())
+            .collect();
+
+        Self {
+            forward: planner.plan_fft_forward(FRAME),
+            inverse: planner.plan_fft_inverse(FRAME),
+            window,
+            frame: vec![0.0; FRAME],
+            pending: Vec::with_capacity(HOP),
+            // Prime the output so every call can return as many samples as it got
+            output: VecDeque::from(vec![0.0; HOP]),
+            overlap: vec![0.0; HOP],
+            noise: vec![0.0; FRAME / 2 + 1],
+            frames_seen: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() == HOP {
+                self.frame.drain(..HOP);
+                self.frame.append(&mut self.pending);
+                self.process_frame();
+            }
+        }
+
+        (0..samples.len())
+            .map(|_| self.output.pop_front().unwrap_or(0.0))
+            .collect()
+    }
+
+    fn process_frame(&mut self) {
+        let mut input: Vec<f32> = self
+            .frame
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, window)| sample * window)
+            .collect();
+        let mut spectrum = self.forward.make_output_vec();
+        if self.forward.process(&mut input, &mut spectrum).is_err() {
+            return;
+        }
+
+        self.frames_seen += 1;
+        for (bin, noise) in spectrum.iter_mut().zip(self.noise.iter_mut()) {
+            let magnitude = bin.norm();
+
+            // Learn the noise floor at first, then follow it: quickly down, slowly up
+            if self.frames_seen <= NOISE_LEARN_FRAMES {
+                *noise += (magnitude - *noise) / self.frames_seen as f32;
+            } else if magnitude < *noise {
+                *noise = 0.9 * *noise + 0.1 * magnitude;
+            } else {
+                *noise = 0.999 * *noise + 0.001 * magnitude;
+            }
+
+            let gain = if magnitude > 0.0 {
+                ((magnitude - OVER_SUBTRACTION * *noise) / magnitude).max(SPECTRAL_FLOOR)
+            } else {
+                SPECTRAL_FLOOR
+            };
+            *bin *= gain;
+        }
+
+        // The inverse transform needs purely real DC and Nyquist bins
+        spectrum[0] = Complex::new(spectrum[0].re, 0.0);
+        spectrum[FRAME / 2] = Complex::new(spectrum[FRAME / 2].re, 0.0);
+
+        let mut frame_out = self.inverse.make_output_vec();
+        if self.inverse.process(&mut spectrum, &mut frame_out).is_err() {
+            return;
+        }
+
+        let scale = 1.0 / FRAME as f32;
+        for (i, sample) in frame_out.iter_mut().enumerate() {
+            *sample *= self.window[i] * scale;
+        }
+
+        self.output.extend(
+            self.overlap
+                .iter()
+                .zip(&frame_out[..HOP])
+                .map(|(previous, current)| previous + current),
+        );
+        self.overlap.copy_from_slice(&frame_out[HOP..]);
+    }
+}
+
+/// Chunks quieter than this are left alone, so silence isn't amplified
+const AGC_GATE: f32 = 0.005;
+/// Most the AGC will amplify
+const AGC_MAX_GAIN: f32 = 10.0;
+
+/// Slow automatic gain control toward a target RMS level
+struct Agc {
+    target: f32,
+    gain: f32,
+}
+
+impl Agc {
+    fn new(target: f32) -> Self {
+        Self { target, gain: 1.0 }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        let start_gain = self.gain;
+        if rms > AGC_GATE {
+            let wanted = (self.target / rms).clamp(1.0 / AGC_MAX_GAIN, AGC_MAX_GAIN);
+            // Turn down quickly to avoid clipping, up slowly to avoid pumping
+            let rate = if wanted < self.gain { 0.5 } else { 0.05 };
+            self.gain += (wanted - self.gain) * rate;
+        }
+
+        // Ramp across the chunk so gain changes don't click
+        let step = (self.gain - start_gain) / samples.len() as f32;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let gain = start_gain + step * (i + 1) as f32;
+            *sample = (*sample * gain).clamp(-1.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::metrics::Metrics;
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// How often the server checks whether it should stop
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest request header read; anything scraping metrics sends far less
+const MAX_REQUEST: usize = 8192;
+
+const OPENMETRICS_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Serves [`Metrics`] over HTTP until dropped
+///
+/// `GET /metrics` returns the OpenMetrics text format for Prometheus, and
+/// `GET /metrics.json` a JSON snapshot for scripts.
+pub struct Exporter {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Exporter {
+    /// Listen on `address` in a background thread
+    pub fn start(address: &str, metrics: Metrics) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .context(format!("Failed to listen for metrics on {}", address))?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        info!(%address, "Serving metrics");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, peer)) => {
+                            // A client that misbehaves only loses its own response
+                            if let Err(e) = serve(stream, &metrics) {
+                                debug!(%peer, "Metrics request failed: {:#}", e);
+                            }
+                        }
+                        // Nothing waiting (or a transient accept error)
+                        Err(_) => thread::sleep(ACCEPT_INTERVAL),
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            address,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address actually listened on, useful when binding port 0
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Answer one request and close the connection
+fn serve(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let request = read_request(&mut stream)?;
+    let mut words = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, target) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+    let path = target.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", OPENMETRICS_TYPE, metrics.snapshot().to_openmetrics()),
+        ("GET", "/metrics.json") => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string_pretty(&metrics.snapshot())? + "\n",
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "Try /metrics or /metrics.json\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Only GET is supported\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Read up to the end of the request headers
+fn read_request(stream: &mut TcpStream) -> Result<String> {
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            anyhow::bail!("Request too long");
+        }
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
@@ -0,0 +1,253 @@
+use crate::audio::Recording;
+use crate::config::FilterConfig;
+use crate::transcribe::Transcription;
+use regex::Regex;
+use std::collections::HashSet;
+
+/// Result of checking a transcription for hallucinations
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Looks like real speech, with non-speech annotations removed
+    Accepted(String),
+    /// Dropped, with a short reason for the history pane
+    Rejected(String),
+}
+
+/// Filters out whisper hallucinations and non-speech tokens
+pub struct HallucinationFilter {
+    enabled: bool,
+    annotations: Option<Regex>,
+    phrases: Vec<String>,
+    max_repetition_ratio: f32,
+    min_voiced_ratio: f32,
+    min_avg_logprob: Option<f32>,
+}
+
+impl HallucinationFilter {
+    pub fn new(config: &FilterConfig) -> Self {
+        let annotations = config.strip_annotations.then(|| {
+            Regex::new(r"\[[^\]]*\]|\([^)]*\)|[♪♫]+").expect("annotation regex is valid")
+        });
+
+        Self {
+            enabled: config.enabled,
+            annotations,
+            phrases: config
+                .hallucination_phrases
+                .iter()
+                .map(|p| normalize(p))
+                .collect(),
+            max_repetition_ratio: config.max_repetition_ratio,
+            min_voiced_ratio: config.min_voiced_ratio,
+            min_avg_logprob: config.min_avg_logprob,
+        }
+    }
+
+    /// Check a raw whisper transcription of the given recording
+    pub fn check(&self, transcription: &Transcription, recording: &Recording) -> Verdict {
+        let text = &transcription.text;
+        if !self.enabled {
+            return Verdict::Accepted(text.to_string());
+        }
+
+        if recording.voiced_ratio < self.min_voiced_ratio {
+            return Verdict::Rejected(format!(
+                "mostly silence ({:.0}% voiced)",
+                recording.voiced_ratio * 100.0
+            ));
+        }
+
+        if let (Some(min), Some(avg)) = (self.min_avg_logprob, transcription.avg_logprob) {
+            if avg < min {
+                return Verdict::Rejected(format!(
+                    "low confidence ({:.0}%)",
+                    avg.exp() * 100.0
+                ));
+            }
+        }
+
+        let cleaned = match self.annotations {
+            Some(ref regex) => strip_annotations(regex, text),
+            None => text.to_string(),
+        };
+        if cleaned.is_empty() {
+            return Verdict::Rejected("non-speech only".to_string());
+        }
+
+        let normalized = normalize(&cleaned);
+        if normalized.is_empty() || self.phrases.contains(&normalized) {
+            return Verdict::Rejected("known hallucination".to_string());
+        }
+
+        let ratio = repetition_ratio(&normalized);
+        if ratio > self.max_repetition_ratio {
+            return Verdict::Rejected(format!("repetitive ({:.0}% repeated)", ratio * 100.0));
+        }
+
+        Verdict::Accepted(cleaned)
+    }
+}
+
+/// Remove bracketed/parenthesized annotations like "[BLANK_AUDIO]" or "(music)"
+fn strip_annotations(regex: &Regex, text: &str) -> String {
+    let stripped = regex.replace_all(text, " ");
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercase and drop punctuation so phrases compare loosely
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_alphanumeric() || c == '\'' { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fraction of repeated word bigrams in normalized text (0.0-1.0)
+///
+/// Natural speech almost never repeats a bigram this much; whisper loops
+/// ("thank you thank you thank you") do.
+pub fn repetition_ratio(normalized: &str) -> f32 {
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    if words.len() < 6 {
+        return 0.0;
+    }
+
+    let bigrams: Vec<_> = words.windows(2).collect();
+    let distinct: HashSet<_> = bigrams.iter().collect();
+    1.0 - distinct.len() as f32 / bigrams.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcription(text: &str, avg_logprob: Option<f32>) -> Transcription {
+        Transcription {
+            text: text.to_string(),
+            segments: Vec::new(),
+            language: None,
+            avg_logprob,
+        }
+    }
+
+    fn recording(voiced_ratio: f32) -> Recording {
+        let mut recording = Recording::from_samples(vec![0.0; 16000], 16000, None).unwrap();
+        recording.voiced_ratio = voiced_ratio;
+        recording
+    }
+
+    fn check(config: &FilterConfig, text: &str) -> Verdict {
+        HallucinationFilter::new(config).check(&transcription(text, None), &recording(1.0))
+    }
+
+    fn accepted(text: &str) -> Verdict {
+        Verdict::Accepted(text.to_string())
+    }
+
+    fn rejected(reason: &str) -> Verdict {
+        Verdict::Rejected(reason.to_string())
+    }
+
+    #[test]
+    fn disabled_filter_accepts_anything() {
+        let config = FilterConfig {
+            enabled: false,
+            ..FilterConfig::default()
+        };
+        let filter = HallucinationFilter::new(&config);
+        let verdict = filter.check(&transcription("[BLANK_AUDIO]", Some(-9.0)), &recording(0.0));
+        assert_eq!(verdict, accepted("[BLANK_AUDIO]"));
+    }
+
+    #[test]
+    fn mostly_silent_recordings_are_rejected() {
+        let filter = HallucinationFilter::new(&FilterConfig::default());
+        let text = transcription("Hello there.", None);
+        assert_eq!(filter.check(&text, &recording(0.19)), rejected("mostly silence (19% voiced)"));
+        assert_eq!(filter.check(&text, &recording(0.2)), accepted("Hello there."));
+    }
+
+    #[test]
+    fn low_confidence_is_rejected_only_when_configured() {
+        let config = FilterConfig {
+            min_avg_logprob: Some(-1.0),
+            ..FilterConfig::default()
+        };
+        let filter = HallucinationFilter::new(&config);
+        let check = |avg_logprob| filter.check(&transcription("Hello there.", avg_logprob), &recording(1.0));
+        assert_eq!(check(Some(-1.01)), rejected("low confidence (36%)"));
+        assert_eq!(check(Some(-1.0)), accepted("Hello there."));
+        // Backends that report no token probabilities aren't judged on them
+        assert_eq!(check(None), accepted("Hello there."));
+
+        let filter = HallucinationFilter::new(&FilterConfig::default());
+        let verdict = filter.check(&transcription("Hello there.", Some(-5.0)), &recording(1.0));
+        assert_eq!(verdict, accepted("Hello there."));
+    }
+
+    #[test]
+    fn annotations_are_stripped() {
+        let config = FilterConfig::default();
+        assert_eq!(check(&config, "[BLANK_AUDIO]"), rejected("non-speech only"));
+        assert_eq!(check(&config, "(music) ♪♫"), rejected("non-speech only"));
+        assert_eq!(check(&config, "(sighs) Hello [laughs] there. ♪"), accepted("Hello there."));
+
+        let config = FilterConfig {
+            strip_annotations: false,
+            ..FilterConfig::default()
+        };
+        assert_eq!(check(&config, "Hello (there)."), accepted("Hello (there)."));
+    }
+
+    #[test]
+    fn known_phrases_are_rejected_only_as_the_whole_output() {
+        let config = FilterConfig::default();
+        assert_eq!(check(&config, "Thank you for watching."), rejected("known hallucination"));
+        assert_eq!(check(&config, " thank you for WATCHING!"), rejected("known hallucination"));
+        assert_eq!(
+            check(&config, "Thank you for watching the demo."),
+            accepted("Thank you for watching the demo.")
+        );
+        assert_eq!(check(&config, "..."), rejected("known hallucination"));
+        // A one-word reply is real dictation unless configured otherwise
+        assert_eq!(check(&config, "You."), accepted("You."));
+
+        let config = FilterConfig {
+            hallucination_phrases: vec!["you".to_string()],
+            ..FilterConfig::default()
+        };
+        assert_eq!(check(&config, "You."), rejected("known hallucination"));
+    }
+
+    #[test]
+    fn looping_output_is_rejected_above_the_ratio() {
+        // Half of the eight word pairs repeat
+        let text = "A b c d a b c d a";
+        assert_eq!(check(&FilterConfig::default(), text), accepted(text));
+
+        let config = FilterConfig {
+            max_repetition_ratio: 0.49,
+            ..FilterConfig::default()
+        };
+        assert_eq!(check(&config, text), rejected("repetitive (50% repeated)"));
+    }
+
+    #[test]
+    fn repetition_ratio_counts_repeated_bigrams() {
+        assert_eq!(repetition_ratio("a b c d a b c d a"), 0.5);
+        assert_eq!(repetition_ratio("thank you thank you thank you"), 0.6);
+        assert_eq!(repetition_ratio("one two three four five six"), 0.0);
+        // Too short to judge
+        assert_eq!(repetition_ratio("you you you you you"), 0.0);
+    }
+
+    #[test]
+    fn normalize_drops_case_and_punctuation() {
+        assert_eq!(normalize("  Don't STOP -- now!  "), "don't stop now");
+        assert_eq!(normalize("[BLANK_AUDIO]"), "blank audio");
+        assert_eq!(normalize("?!"), "");
+    }
+}
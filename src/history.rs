@@ -0,0 +1,256 @@
+use crate::metrics::Timings;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// One transcription as stored in the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub timestamp: DateTime<Local>,
+    /// Text after filtering and post-processing (whisper's output if rejected)
+    pub text: String,
+    /// Whisper's output before filtering and post-processing
+    pub raw_text: String,
+    /// Length of the recording (seconds)
+    pub duration: f32,
+    /// Time from the end of the recording until the text was typed (seconds)
+    pub latency: f32,
+    /// How long each stage took
+    #[serde(default)]
+    pub timings: Timings,
+    /// Highest absolute input sample of the recording (0.0-1.0)
+    #[serde(default)]
+    pub peak: f32,
+    /// Input samples of the recording at or beyond full scale
+    #[serde(default)]
+    pub clipped_samples: usize,
+    /// Model file the transcription came from
+    pub model: String,
+    /// Language whisper reported, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Whisper's average token probability (0.0-1.0), if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Title of the window that was focused when typing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    /// Whether the text was typed into the window
+    pub typed: bool,
+    /// Why the hallucination filter dropped this entry, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<String>,
+    /// Kept recording, if `audio.keep_recordings` was on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<PathBuf>,
+}
+
+/// Append-only JSONL file of every transcription
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn open(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(shellexpand::tilde(path).to_string()),
+        }
+    }
+
+    /// Append a record to the end of the file
+    pub fn append(&self, record: &HistoryRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open history file at {:?}", self.path))?;
+
+        // Write the whole line at once so a crash never leaves half a record
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Every record, oldest first
+    ///
+    /// Lines that can't be parsed are skipped rather than failing the load.
+    pub fn load(&self) -> Result<Vec<HistoryRecord>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).context(format!("Failed to open history file at {:?}", self.path))
+            }
+        };
+
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if let Ok(record) = serde_json::from_str(&line) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Remove the record saved at `timestamp`
+    pub fn remove(&self, timestamp: &DateTime<Local>) -> Result<()> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(e).context(format!("Failed to read history file at {:?}", self.path))
+            }
+        };
+
+        // Lines that don't parse are kept as they are
+        let kept: String = content
+            .lines()
+            .filter(|line| {
+                serde_json::from_str::<HistoryRecord>(line)
+                    .map_or(true, |record| record.timestamp != *timestamp)
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        // Replace the file in one step so a crash can't truncate the history
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp_path, kept)?;
+        fs::rename(&temp_path, &self.path)
+            .context(format!("Failed to rewrite history file at {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    /// The `limit` most recent records, oldest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<HistoryRecord>> {
+        let mut records = self.load()?;
+        let skip = records.len().saturating_sub(limit);
+        records.drain(..skip);
+        Ok(records)
+    }
+}
+
+/// Which history records to show
+#[derive(Debug, Default)]
+pub struct Query {
+    /// Case-insensitive text to look for in the final or raw text
+    pub search: Option<String>,
+    /// First day to include
+    pub since: Option<NaiveDate>,
+    /// Last day to include
+    pub until: Option<NaiveDate>,
+    /// Include transcriptions the hallucination filter rejected
+    pub include_rejected: bool,
+}
+
+impl Query {
+    pub fn matches(&self, record: &HistoryRecord) -> bool {
+        if record.rejected.is_some() && !self.include_rejected {
+            return false;
+        }
+
+        let day = record.timestamp.date_naive();
+        if self.since.is_some_and(|since| day < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| day > until) {
+            return false;
+        }
+
+        match self.search {
+            Some(ref search) => {
+                let search = search.to_lowercase();
+                record.text.to_lowercase().contains(&search)
+                    || record.raw_text.to_lowercase().contains(&search)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Output format for `thehand history`
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ExportFormat {
+    /// One readable line per transcription
+    #[default]
+    Text,
+    /// One JSON record per line, as stored
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+/// Write records in the given format
+pub fn export(records: &[HistoryRecord], format: ExportFormat, out: &mut dyn Write) -> Result<()> {
+    match format {
+        ExportFormat::Text => {
+            for record in records {
+                let timestamp = record.timestamp.format("%Y-%m-%d %H:%M:%S");
+                match record.rejected {
+                    Some(ref reason) => {
+                        writeln!(out, "[{}] ({}) {}", timestamp, reason, record.text)?
+                    }
+                    None => writeln!(out, "[{}] {}", timestamp, record.text)?,
+                }
+            }
+        }
+        ExportFormat::Jsonl => {
+            for record in records {
+                writeln!(out, "{}", serde_json::to_string(record)?)?;
+            }
+        }
+        ExportFormat::Csv => {
+            writeln!(
+                out,
+                "timestamp,text,raw_text,duration,latency,peak,clipped_samples,model,language,confidence,window,typed,rejected,audio"
+            )?;
+            for record in records {
+                let fields = [
+                    record.timestamp.to_rfc3339(),
+                    record.text.clone(),
+                    record.raw_text.clone(),
+                    format!("{:.2}", record.duration),
+                    format!("{:.2}", record.latency),
+                    format!("{:.3}", record.peak),
+                    record.clipped_samples.to_string(),
+                    record.model.clone(),
+                    record.language.clone().unwrap_or_default(),
+                    record.confidence.map(|c| format!("{:.3}", c)).unwrap_or_default(),
+                    record.window.clone().unwrap_or_default(),
+                    record.typed.to_string(),
+                    record.rejected.clone().unwrap_or_default(),
+                    record
+                        .audio
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_default(),
+                ];
+                let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote a CSV field when it contains a separator, quote or newline
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
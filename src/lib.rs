@@ -0,0 +1,57 @@
+//! Voice-activated transcription that types directly into your focused window
+//!
+//! The [`Pipeline`] ties the stages together: audio capture from any
+//! [`AudioSource`] with optional clean-up ([`AudioCapture`], [`dsp`]), voice
+//! activity detection ([`Vad`]), whisper ([`Transcriber`]), the hallucination
+//! filter, post-processing ([`PostProcessor`]) and an [`OutputSink`] that
+//! receives the text.
+//!
+//! ```no_run
+//! use thehand::{Config, Pipeline, PipelineEvent};
+//!
+//! let mut pipeline = Pipeline::builder(Config::load()?).build()?;
+//! loop {
+//!     for event in pipeline.poll() {
+//!         if let PipelineEvent::Transcribed(record) = event {
+//!             println!("{}", record.text);
+//!         }
+//!     }
+//!     std::thread::sleep(std::time::Duration::from_millis(50));
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod archive;
+pub mod audio;
+pub mod config;
+pub mod control;
+pub mod dsp;
+pub mod exporter;
+pub mod filter;
+pub mod history;
+pub mod metrics;
+pub mod pipeline;
+pub mod postprocess;
+pub mod runtime;
+pub mod source;
+pub mod status;
+pub mod streaming;
+pub mod transcribe;
+pub mod typing;
+pub mod vad;
+pub mod wake;
+#[cfg(feature = "whisper-rs")]
+pub mod whisper_local;
+
+pub use audio::{AudioCapture, AudioEvent, Recording};
+pub use config::Config;
+pub use filter::{HallucinationFilter, Verdict};
+pub use history::HistoryRecord;
+pub use metrics::{Metrics, Stats, Timings};
+pub use pipeline::{Pipeline, PipelineBuilder, PipelineEvent};
+pub use postprocess::PostProcessor;
+pub use source::{AudioSource, CpalSource, FileSource, PcmSource, SyntheticSource};
+pub use transcribe::{TranscribeError, Transcriber, Transcription, TranscriptionJob};
+pub use typing::{KeyboardSink, OutputSink};
+pub use vad::{Vad, VadEvent};
+pub use wake::{Spotter, WakePhrase};
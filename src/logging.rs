@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use thehand::config::{LogLevel, LoggingConfig};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+
+/// Lines the log pane keeps; more than any terminal is tall
+const PANE_LINES: usize = 500;
+
+/// Recent log lines for the TUI log pane, newest last
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogBuffer {
+    /// The last `count` lines, oldest first
+    pub fn tail(&self, count: usize) -> Vec<String> {
+        let Ok(lines) = self.lines.lock() else { return Vec::new() };
+        lines.iter().skip(lines.len().saturating_sub(count)).cloned().collect()
+    }
+}
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The formatter writes each event in one go, so lines arrive whole
+        if let Ok(mut lines) = self.lines.lock() {
+            for line in String::from_utf8_lossy(buf).lines() {
+                if lines.len() == PANE_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(line.to_string());
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Local time in a chrono format
+struct LocalTime(&'static str);
+
+impl FormatTime for LocalTime {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", Local::now().format(self.0))
+    }
+}
+
+/// Diagnostic log directory: `logging.dir`, else `$XDG_STATE_HOME/thehand`
+pub fn dir(config: &LoggingConfig) -> Result<PathBuf> {
+    if !config.dir.is_empty() {
+        return Ok(PathBuf::from(&config.dir));
+    }
+    let state = match std::env::var_os("XDG_STATE_HOME") {
+        Some(state) if !state.is_empty() => PathBuf::from(state),
+        _ => {
+            let home = std::env::var("HOME").context("HOME environment variable not set")?;
+            PathBuf::from(home).join(".local/state")
+        }
+    };
+    Ok(state.join("thehand"))
+}
+
+/// Log to a daily file in the log directory and to the returned pane buffer
+///
+/// Each `verbose` step raises the configured level once. Keep the guard alive
+/// until exit so buffered lines reach the file.
+pub fn init(config: &LoggingConfig, verbose: u8) -> Result<(LogBuffer, WorkerGuard)> {
+    let dir = dir(config)?;
+    std::fs::create_dir_all(&dir).context(format!("Failed to create log directory {:?}", dir))?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("thehand")
+        .filename_suffix("log")
+        .max_log_files(config.max_files)
+        .build(&dir)
+        .context(format!("Failed to open a log file in {:?}", dir))?;
+    let (file, guard) = tracing_appender::non_blocking(appender);
+
+    let level = (0..verbose).fold(config.level, |level, _| level.more_verbose());
+    // Only our own messages; dependencies just for warnings
+    let filter = Targets::new()
+        .with_target("thehand", level_filter(level))
+        .with_default(LevelFilter::WARN);
+
+    let buffer = LogBuffer::default();
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(file)
+                .with_ansi(false)
+                .with_timer(LocalTime("%Y-%m-%d %H:%M:%S%.3f")),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(buffer.clone())
+                .with_ansi(false)
+                .with_target(false)
+                .with_timer(LocalTime("%H:%M:%S")),
+        )
+        .try_init()
+        .context("Failed to set up logging")?;
+
+    Ok((buffer, guard))
+}
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    }
+}
@@ -1,28 +1,60 @@
-mod audio;
-mod config;
+mod cli;
+mod logging;
 mod state;
-mod transcribe;
-mod typing;
 mod ui;
 
-use anyhow::Result;
-use audio::{AudioCapture, AudioEvent};
-use config::Config;
+use anyhow::{Context, Result};
+use clap::Parser;
+use cli::{BenchArgs, Cli, Command, HistoryArgs, InputArgs, RetranscribeArgs};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use logging::LogBuffer;
+use state::AppStateContainer;
 use ratatui::{backend::CrosstermBackend, Terminal};
-use state::{AppState, AppStateContainer};
-use std::fs::OpenOptions;
-use std::io::{self, Write};
+use std::fs::File;
+use std::io;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::process::Child;
+use std::time::{Duration, Instant};
+use thehand::history::{self, HistoryRecord, HistoryStore, Query};
+use thehand::status::AppState;
+use thehand::control::{self, ControlCommand, ControlSocket};
+use thehand::{archive, metrics, runtime, transcribe};
+use thehand::{
+    Config, HallucinationFilter, Pipeline, PostProcessor, Recording, Timings, Transcriber, Verdict,
+};
 
 fn main() -> Result<()> {
-    // Load configuration
-    let config = match Config::load() {
+    let cli = Cli::parse();
+
+    match cli.command {
+        // Only reads the config, so it doesn't need whisper installed
+        Some(Command::History(ref args)) => return show_history(&Config::load()?, args),
+        Some(Command::Retranscribe(ref args)) => {
+            let mut config = Config::load()?;
+            cli.whisper.apply(&mut config);
+            config.validate()?;
+            return retranscribe(&config, args);
+        }
+        Some(Command::Bench(ref args)) => {
+            let mut config = Config::load()?;
+            cli.whisper.apply(&mut config);
+            config.validate()?;
+            return bench(&config, args);
+        }
+        Some(Command::Cancel) => return control::send(&control::socket_path()?, ControlCommand::Cancel),
+        None => {}
+    }
+
+    // Load configuration, then apply command-line overrides
+    let config = match Config::load().and_then(|mut cfg| {
+        cli.whisper.apply(&mut cfg);
+        cfg.validate()?;
+        Ok(cfg)
+    }) {
         Ok(cfg) => cfg,
         Err(e) => {
             eprintln!("Error loading configuration: {}", e);
@@ -34,8 +66,20 @@ fn main() -> Result<()> {
         }
     };
 
+    // Diagnostics go to a log file, since the TUI owns the terminal
+    let (logs, log_guard) = match logging::init(&config.logging, cli.verbose) {
+        Ok(logging) => logging,
+        Err(e) => {
+            eprintln!("Error setting up logging: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Run the application
-    if let Err(e) = run_app(config) {
+    if let Err(e) = run_app(config, &cli.input, &logs) {
+        tracing::error!("Application error: {:#}", e);
+        // Exiting skips destructors, so flush the log first
+        drop(log_guard);
         eprintln!("Application error: {}", e);
         std::process::exit(1);
     }
@@ -43,7 +87,13 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_app(config: Config) -> Result<()> {
+fn run_app(config: Config, input: &InputArgs, logs: &LogBuffer) -> Result<()> {
+    let mut builder = Pipeline::builder(config.clone());
+    if let Some(source) = input.source(config.audio.sample_rate)? {
+        builder = builder.boxed_source(source);
+    }
+    let mut pipeline = builder.build()?;
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -51,20 +101,28 @@ fn run_app(config: Config) -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app state
+    // Create app state, picking up where the last session left off
+    let config = pipeline.config();
     let mut app = AppStateContainer::new(config.ui.history_limit);
+    app.set_thresholds(config.audio.voice_threshold, config.audio.silence_threshold);
+    if let Some(store) = pipeline.history() {
+        match store.recent(config.ui.history_limit) {
+            Ok(records) => app.load_history(&records),
+            Err(e) => app.status.set_error(format!("Failed to load history: {}", e)),
+        }
+    }
 
-    // Initialize audio capture
-    let audio = AudioCapture::new(
-        config.audio.voice_threshold,
-        config.audio.silence_threshold,
-        config.audio.silence_duration,
-        config.audio.min_speech_duration,
-        config.audio.sample_rate,
-    )?;
+    // Lets `thehand cancel` reach this instance; the TUI works without it
+    let control = match control::socket_path().and_then(|path| ControlSocket::bind(&path)) {
+        Ok(control) => Some(control),
+        Err(e) => {
+            tracing::warn!("Remote control unavailable: {:#}", e);
+            None
+        }
+    };
 
     // Main loop
-    let result = main_loop(&mut terminal, &mut app, &audio, &config);
+    let result = main_loop(&mut terminal, &mut app, &mut pipeline, control.as_ref(), logs);
 
     // Restore terminal
     disable_raw_mode()?;
@@ -81,129 +139,351 @@ fn run_app(config: Config) -> Result<()> {
 fn main_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     app: &mut AppStateContainer,
-    audio: &AudioCapture,
-    config: &Config,
+    pipeline: &mut Pipeline,
+    control: Option<&ControlSocket>,
+    logs: &LogBuffer,
 ) -> Result<()> {
-    let mut pending_transcription: Option<PathBuf> = None;
+    app.status.set_state(AppState::LoadingModel);
+
+    // History entry waiting to be re-typed once the user has switched windows
+    let mut pending_retype: Option<(String, Instant)> = None;
+    // Kept open for the session; on X11 copied text vanishes with its owner
+    let mut clipboard: Option<arboard::Clipboard> = None;
+    // Kept recording being played back
+    let mut player: Option<Child> = None;
 
     loop {
         // Draw UI
-        terminal.draw(|f| ui::render(f, app))?;
+        terminal.draw(|f| ui::render(f, app, logs))?;
 
         // Handle keyboard events (non-blocking)
         if event::poll(Duration::from_millis(50))? {
             if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Char('Q') => {
-                        app.should_quit = true;
-                        break;
-                    }
-                    KeyCode::Char('m') | KeyCode::Char('M') => {
-                        app.toggle_mute();
+                if app.editing_filter {
+                    match key.code {
+                        KeyCode::Enter => app.finish_filter(),
+                        KeyCode::Esc => app.clear_filter(),
+                        KeyCode::Backspace => app.pop_filter_char(),
+                        KeyCode::Char(c) => app.push_filter_char(c),
+                        _ => {}
                     }
-                    KeyCode::Char('c') | KeyCode::Char('C') => {
-                        if audio.is_recording() {
-                            audio.cancel_recording();
-                            app.set_state(AppState::Idle);
-                            app.clear_current_text();
+                } else {
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Char('Q') => {
+                            app.should_quit = true;
+                            break;
                         }
+                        KeyCode::Char('m') | KeyCode::Char('M') => pipeline.set_muted(!pipeline.is_muted()),
+                        KeyCode::Char('c') | KeyCode::Char('C') => pipeline.cancel(),
+                        KeyCode::Up | KeyCode::Char('k') => app.move_history_selection(-1),
+                        KeyCode::Down | KeyCode::Char('j') => app.move_history_selection(1),
+                        KeyCode::PageUp => app.move_history_selection(-(app.history_page as isize)),
+                        KeyCode::PageDown => app.move_history_selection(app.history_page as isize),
+                        KeyCode::Enter => {
+                            if let Some(entry) = app.selected_history() {
+                                pending_retype = Some((entry.text.clone(), Instant::now()));
+                            }
+                        }
+                        KeyCode::Esc if pending_retype.is_some() => {
+                            pending_retype = None;
+                            app.status.clear_current_text();
+                        }
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            if let Some(entry) = app.selected_history() {
+                                let text = entry.text.clone();
+                                match copy_to_clipboard(&mut clipboard, text) {
+                                    Ok(()) => app.status.set_current_text("Copied to clipboard".to_string()),
+                                    Err(e) => app.status.set_error(format!("Failed to copy: {}", e)),
+                                }
+                            }
+                        }
+                        KeyCode::Char('p') | KeyCode::Char('P') => {
+                            // A second press stops playback
+                            if let Some(mut child) = player.take() {
+                                let _ = child.kill();
+                                let _ = child.wait();
+                            } else if let Some(entry) = app.selected_history() {
+                                match entry.audio {
+                                    Some(ref path) => match archive::play(path) {
+                                        Ok(child) => player = Some(child),
+                                        Err(e) => app.status.set_error(e.to_string()),
+                                    },
+                                    None => app.status.set_error("No recording kept for this entry".to_string()),
+                                }
+                            }
+                        }
+                        KeyCode::Char('d') | KeyCode::Char('D') => {
+                            if let Some(entry) = app.remove_selected_history() {
+                                if let Some(store) = pipeline.history() {
+                                    if let Err(e) = store.remove(&entry.timestamp) {
+                                        app.status.set_error(format!("Failed to delete from history: {}", e));
+                                    }
+                                }
+                                if let Some(ref path) = entry.audio {
+                                    let _ = std::fs::remove_file(path);
+                                }
+                            }
+                        }
+                        KeyCode::Char('/') => app.start_filter(),
+                        KeyCode::Char('s') | KeyCode::Char('S') => app.toggle_stats(),
+                        KeyCode::Char('l') | KeyCode::Char('L') => app.toggle_log(),
+                        _ => {}
                     }
-                    _ => {}
                 }
             }
         }
 
-        // Handle audio events
-        while let Some(event) = audio.poll_event() {
-            match event {
-                AudioEvent::Level(level) => {
-                    if app.state != AppState::Muted {
-                        app.update_audio_level(level);
-                    }
-                }
-                AudioEvent::VoiceDetected => {
-                    if app.state != AppState::Muted {
-                        app.clear_error();
-                    }
-                }
-                AudioEvent::RecordingStarted => {
-                    if app.state != AppState::Muted {
-                        app.set_state(AppState::Recording);
-                        app.clear_current_text();
-                    }
-                }
-                AudioEvent::RecordingStopped(path) => {
-                    if app.state != AppState::Muted {
-                        app.set_state(AppState::Transcribing);
-                        pending_transcription = Some(path);
-                    }
-                }
-                AudioEvent::SilenceDetected => {
-                    // Just for informational purposes
-                }
-                AudioEvent::Error(msg) => {
-                    app.set_error(msg);
-                    app.set_state(AppState::Idle);
-                }
+        // Commands from other processes
+        for command in control.map(ControlSocket::poll).unwrap_or_default() {
+            match command {
+                ControlCommand::Cancel => pipeline.cancel(),
             }
         }
 
-        // Handle transcription if pending
-        if let Some(audio_path) = pending_transcription.take() {
-            match transcribe::transcribe(
-                &config.whisper.binary_path,
-                &config.whisper.model_path,
-                &audio_path,
-            ) {
-                Ok(text) => {
-                    app.set_current_text(text.clone());
-                    app.set_state(AppState::Typing);
-
-                    // Type the text
-                    if let Err(e) = typing::type_text(&text, config.typing.keystroke_delay) {
-                        app.set_error(format!("Failed to type text: {}", e));
-                    } else {
-                        // Add to history
-                        app.add_to_history(text.clone());
-
-                        // Log to file if enabled
-                        if config.ui.log_to_file {
-                            let _ = log_transcription(&config.ui.log_path, &text);
-                        }
-                    }
+        // Forget playback once it has finished
+        if player
+            .as_mut()
+            .is_some_and(|child| !matches!(child.try_wait(), Ok(None)))
+        {
+            player = None;
+        }
 
-                    app.set_state(AppState::Idle);
-                    app.clear_current_text();
+        // Re-type a history entry once the countdown is over
+        if let Some((ref text, requested)) = pending_retype {
+            let remaining = RETYPE_DELAY.saturating_sub(requested.elapsed());
+            if remaining.is_zero() {
+                if let Err(e) = pipeline.type_text(text) {
+                    app.status.set_error(format!("Failed to type text: {}", e));
                 }
-                Err(e) => {
-                    app.set_error(format!("Transcription failed: {}", e));
-                    app.set_state(AppState::Idle);
+                // If TheHand itself was still focused, don't act on the typed keys
+                while event::poll(Duration::ZERO)? {
+                    event::read()?;
                 }
+                pending_retype = None;
+                app.status.clear_current_text();
+            } else {
+                app.status.set_current_text(format!(
+                    "Re-typing in {:.0}s, focus the target window (Esc to cancel)",
+                    remaining.as_secs_f32().ceil()
+                ));
             }
+        }
+
+        // Handle pipeline events
+        for event in pipeline.poll() {
+            app.handle_event(event, pipeline.config());
+        }
+        pipeline.metrics().enter_state(app.status.state);
+    }
+
+    if let Some(mut child) = player {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    Ok(())
+}
+
+/// Time to switch to the target window before a history entry is re-typed
+const RETYPE_DELAY: Duration = Duration::from_secs(3);
+
+/// Copy text to the clipboard, connecting to it on first use
+fn copy_to_clipboard(clipboard: &mut Option<arboard::Clipboard>, text: String) -> Result<()> {
+    let clipboard = match clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.insert(arboard::Clipboard::new()?),
+    };
+    clipboard.set_text(text)?;
+    Ok(())
+}
+
+/// `thehand history`: print matching transcriptions from the history file
+fn show_history(config: &Config, args: &HistoryArgs) -> Result<()> {
+    let store = HistoryStore::open(&config.history.path);
+    let query = Query {
+        search: args.search.clone(),
+        since: args.since,
+        until: args.until,
+        include_rejected: args.all,
+    };
+
+    let mut records: Vec<HistoryRecord> = store
+        .load()?
+        .into_iter()
+        .filter(|record| query.matches(record))
+        .collect();
+    if let Some(limit) = args.limit {
+        let skip = records.len().saturating_sub(limit);
+        records.drain(..skip);
+    }
+
+    match args.output {
+        Some(ref path) => {
+            let mut file =
+                File::create(path).context(format!("Failed to create {:?}", path))?;
+            history::export(&records, args.format, &mut file)
+        }
+        None => history::export(&records, args.format, &mut io::stdout().lock()),
+    }
+}
+
+/// `thehand retranscribe`: run kept recordings through whisper again
+fn retranscribe(config: &Config, args: &RetranscribeArgs) -> Result<()> {
+    let store = HistoryStore::open(&config.history.path);
+    let query = Query {
+        search: args.search.clone(),
+        include_rejected: true,
+        ..Query::default()
+    };
 
-            // Clean up audio file
-            let _ = transcribe::cleanup_audio_file(&audio_path);
+    let mut records: Vec<HistoryRecord> = store
+        .load()?
+        .into_iter()
+        .filter(|record| record.audio.as_ref().is_some_and(|path| path.exists()))
+        .filter(|record| query.matches(record))
+        .collect();
+    let skip = records.len().saturating_sub(args.limit);
+    records.drain(..skip);
+    if records.is_empty() {
+        anyhow::bail!("No kept recordings match; enable audio.keep_recordings first");
+    }
+
+    let transcriber = Transcriber::load(&config.whisper)?;
+    let model = transcribe::model_name(&config.whisper.model_path);
+    let wav_dir = if config.whisper.backend.needs_wav() {
+        Some(runtime::dir()?)
+    } else {
+        None
+    };
+
+    for record in &records {
+        let Some(ref path) = record.audio else { continue };
+        let (samples, sample_rate) = archive::load(path)?;
+
+        // The CLI backend gets a fresh WAV, whatever format the recording was kept in
+        let recording = Recording::from_samples(samples, sample_rate, wav_dir.as_deref())?;
+        let result = transcriber.transcribe(&recording);
+        recording.discard();
+
+        println!("[{}] {}", record.timestamp.format("%Y-%m-%d %H:%M:%S"), path.display());
+        println!("  {:<20} {}", record.model, record.raw_text);
+        match result {
+            Ok(transcription) => println!("  {:<20} {}", model, transcription.text),
+            Err(e) => println!("  {:<20} ({})", model, e),
         }
     }
 
     Ok(())
 }
 
-fn log_transcription(log_path: &str, text: &str) -> Result<()> {
-    let path = shellexpand::tilde(log_path).to_string();
+/// Transcribe every recording in a directory with each model and report the timings
+fn bench(config: &Config, args: &BenchArgs) -> Result<()> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&args.dir)
+        .with_context(|| format!("Failed to read {}", args.dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| matches!(extension, "wav" | "flac" | "opus"))
+        })
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        anyhow::bail!("No WAV, FLAC or Opus files in {}", args.dir.display());
+    }
 
-    // Create parent directory if needed
-    if let Some(parent) = std::path::Path::new(&path).parent() {
-        std::fs::create_dir_all(parent)?;
+    // Decode once up front so it doesn't count against any model
+    let mut inputs = Vec::new();
+    for path in &paths {
+        let (samples, sample_rate) = archive::load(path)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        inputs.push((name, samples, sample_rate));
     }
+    let name_width = inputs.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+
+    let models = if args.models.is_empty() {
+        vec![config.whisper.model_path.clone()]
+    } else {
+        args.models.iter().map(|model| shellexpand::tilde(model).to_string()).collect()
+    };
+    let filter = HallucinationFilter::new(&config.filter);
+    let postprocessor = PostProcessor::new(&config.postprocess)?;
+    let wav_dir = if config.whisper.backend.needs_wav() {
+        Some(runtime::dir()?)
+    } else {
+        None
+    };
+
+    for model in &models {
+        let mut whisper = config.whisper.clone();
+        whisper.model_path = model.clone();
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&path)?;
+        let load_started = Instant::now();
+        let transcriber = Transcriber::load(&whisper)?;
+        println!(
+            "{} (loaded in {})",
+            transcribe::model_name(model),
+            metrics::seconds(load_started.elapsed().as_secs_f32())
+        );
 
-    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-    writeln!(file, "[{}] {}", timestamp, text)?;
+        let mut results = Vec::new();
+        for (name, samples, sample_rate) in &inputs {
+            let recording = Recording::from_samples(samples.clone(), *sample_rate, wav_dir.as_deref())?;
+            let mut timings = Timings {
+                wav_write: recording.wav_write,
+                ..Timings::default()
+            };
+
+            let started = Instant::now();
+            let result = transcriber.transcribe(&recording);
+            timings.transcription = started.elapsed().as_secs_f32();
+            timings.real_time_factor = timings.transcription / recording.duration().max(f32::EPSILON);
+
+            let postprocess_started = Instant::now();
+            let text = result.map(|transcription| match filter.check(&transcription, &recording) {
+                Verdict::Accepted(cleaned) => postprocessor.apply(&cleaned),
+                Verdict::Rejected(reason) => format!("(rejected: {})", reason),
+            });
+            timings.postprocess = postprocess_started.elapsed().as_secs_f32();
+            recording.discard();
+
+            // Failed runs stay out of the average
+            if text.is_ok() {
+                results.push(timings);
+            }
+            let text = text.unwrap_or_else(|e| format!("({})", e));
+            println!(
+                "  {:<width$}  {:>6}  {}  {}",
+                name,
+                metrics::seconds(recording.duration()),
+                bench_timings(&timings),
+                text,
+                width = name_width
+            );
+        }
+
+        if let Some(average) = metrics::average(results.iter()) {
+            println!(
+                "  {:<width$}  {:>6}  {}",
+                "average",
+                "",
+                bench_timings(&average),
+                width = name_width
+            );
+        }
+        println!();
+    }
 
     Ok(())
 }
+
+/// The stages `bench` measures; there is no silence wait, queue or typing
+fn bench_timings(timings: &Timings) -> String {
+    format!(
+        "wav {:>5}  whisper {:>6}  RTF {:.2}  post {:>5}",
+        metrics::seconds(timings.wav_write),
+        metrics::seconds(timings.transcription),
+        timings.real_time_factor,
+        metrics::seconds(timings.postprocess)
+    )
+}
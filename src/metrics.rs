@@ -0,0 +1,515 @@
+use crate::history::HistoryRecord;
+use crate::status::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How long each stage of one utterance took (seconds)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timings {
+    /// Silence waited out after speech ended, before the recording stopped
+    pub silence_wait: f32,
+    /// Saving the recording for whisper
+    pub wav_write: f32,
+    /// Waiting for an earlier transcription to finish
+    pub queued: f32,
+    /// Checking the opening for the wake phrase, if that is on
+    pub wake: f32,
+    /// Whisper's wall time
+    pub transcription: f32,
+    /// Transcription time divided by audio length; below 1.0 is faster than real time
+    pub real_time_factor: f32,
+    /// Filtering and post-processing
+    pub postprocess: f32,
+    /// Typing the text
+    pub typing: f32,
+}
+
+impl Timings {
+    /// Time from the end of speech until the text was typed
+    pub fn total(&self) -> f32 {
+        self.silence_wait
+            + self.wav_write
+            + self.queued
+            + self.wake
+            + self.transcription
+            + self.postprocess
+            + self.typing
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "silence {} · wav {} · queue {} · ",
+            seconds(self.silence_wait),
+            seconds(self.wav_write),
+            seconds(self.queued)
+        )?;
+        if self.wake > 0.0 {
+            write!(f, "wake {} · ", seconds(self.wake))?;
+        }
+        write!(
+            f,
+            "whisper {} (RTF {:.2}) · post {} · typing {} · total {}",
+            seconds(self.transcription),
+            self.real_time_factor,
+            seconds(self.postprocess),
+            seconds(self.typing),
+            seconds(self.total())
+        )
+    }
+}
+
+/// Short human-readable duration: milliseconds below a second
+pub fn seconds(value: f32) -> String {
+    if value < 1.0 {
+        format!("{:.0}ms", value * 1000.0)
+    } else {
+        format!("{:.2}s", value)
+    }
+}
+
+/// Utterances the rolling average covers
+const STATS_WINDOW: usize = 20;
+
+/// The latest timings and a rolling average over recent utterances
+#[derive(Debug, Default)]
+pub struct Stats {
+    recent: VecDeque<Timings>,
+}
+
+impl Stats {
+    pub fn push(&mut self, timings: Timings) {
+        if self.recent.len() == STATS_WINDOW {
+            self.recent.pop_back();
+        }
+        self.recent.push_front(timings);
+    }
+
+    pub fn last(&self) -> Option<&Timings> {
+        self.recent.front()
+    }
+
+    /// Number of utterances in the average
+    pub fn count(&self) -> usize {
+        self.recent.len()
+    }
+
+    /// Mean of each stage over the recent utterances
+    pub fn average(&self) -> Option<Timings> {
+        average(self.recent.iter())
+    }
+}
+
+/// Mean of each stage, or `None` without any timings
+pub fn average<'a>(timings: impl Iterator<Item = &'a Timings>) -> Option<Timings> {
+    let mut sum = Timings::default();
+    let mut count = 0;
+    for timings in timings {
+        sum.silence_wait += timings.silence_wait;
+        sum.wav_write += timings.wav_write;
+        sum.queued += timings.queued;
+        sum.wake += timings.wake;
+        sum.transcription += timings.transcription;
+        sum.real_time_factor += timings.real_time_factor;
+        sum.postprocess += timings.postprocess;
+        sum.typing += timings.typing;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+
+    let n = count as f32;
+    Some(Timings {
+        silence_wait: sum.silence_wait / n,
+        wav_write: sum.wav_write / n,
+        queued: sum.queued / n,
+        wake: sum.wake / n,
+        transcription: sum.transcription / n,
+        real_time_factor: sum.real_time_factor / n,
+        postprocess: sum.postprocess / n,
+        typing: sum.typing / n,
+    })
+}
+
+/// Why something went wrong, for counting errors by kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The audio source failed or a recording couldn't be saved
+    Capture,
+    /// Whisper failed or produced unusable output
+    Transcription,
+    /// Whisper ran past its timeout
+    Timeout,
+    /// The text couldn't be typed
+    Typing,
+    /// The history file couldn't be written
+    History,
+    /// A recording couldn't be kept or the archive pruned
+    Archive,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 6] = [
+        ErrorKind::Capture,
+        ErrorKind::Transcription,
+        ErrorKind::Timeout,
+        ErrorKind::Typing,
+        ErrorKind::History,
+        ErrorKind::Archive,
+    ];
+
+    /// Machine-readable name, used as a metrics label
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Capture => "capture",
+            ErrorKind::Transcription => "transcription",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Typing => "typing",
+            ErrorKind::History => "history",
+            ErrorKind::Archive => "archive",
+        }
+    }
+}
+
+/// How an utterance ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Transcribed and typed
+    Typed,
+    /// Dropped by the hallucination filter
+    Rejected,
+    /// Transcribed, but typing failed
+    NotTyped,
+    /// Whisper failed or timed out
+    Failed,
+    /// Cancelled while recording or transcribing
+    Cancelled,
+    /// Didn't start with the wake phrase
+    Ignored,
+}
+
+impl Outcome {
+    pub const ALL: [Outcome; 6] = [
+        Outcome::Typed,
+        Outcome::Rejected,
+        Outcome::NotTyped,
+        Outcome::Failed,
+        Outcome::Cancelled,
+        Outcome::Ignored,
+    ];
+
+    /// Machine-readable name, used as a metrics label
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Typed => "typed",
+            Outcome::Rejected => "rejected",
+            Outcome::NotTyped => "not_typed",
+            Outcome::Failed => "failed",
+            Outcome::Cancelled => "cancelled",
+            Outcome::Ignored => "ignored",
+        }
+    }
+}
+
+/// Upper bounds of the stage latency histogram buckets (seconds)
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+/// Upper bounds of the real-time factor histogram buckets
+const RTF_BUCKETS: &[f64] = &[0.05, 0.1, 0.2, 0.3, 0.5, 0.75, 1.0, 1.5, 2.0];
+
+/// Distribution of observed values over fixed buckets
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    #[serde(skip)]
+    bounds: &'static [f64],
+    /// Observations in each bucket, the last one past every bound
+    #[serde(skip)]
+    counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+    /// Cumulative counts at or below each bound
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Bucket {
+    le: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0.0,
+            buckets: Vec::new(),
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Upper bound and cumulative count of each bucket
+    fn cumulative(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds
+            .iter()
+            .zip(self.counts.iter().scan(0, |total, &count| {
+                *total += count;
+                Some(*total)
+            }))
+            .map(|(&bound, count)| (bound, count))
+    }
+
+    /// Fill in the cumulative buckets for serialization
+    fn finish(&mut self) {
+        self.buckets = self
+            .cumulative()
+            .map(|(le, count)| Bucket { le, count })
+            .collect();
+    }
+}
+
+/// Everything [`Metrics`] has counted so far
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// Time since the pipeline started (seconds)
+    pub uptime_seconds: f64,
+    /// Current state of the front end, if it reports one
+    pub state: Option<&'static str>,
+    /// Finished utterances by outcome
+    pub utterances: BTreeMap<&'static str, u64>,
+    /// Errors by kind
+    pub errors: BTreeMap<&'static str, u64>,
+    /// Audio recorded and transcribed (seconds)
+    pub audio_seconds: f64,
+    /// Time spent in each front end state (seconds)
+    pub state_seconds: BTreeMap<&'static str, f64>,
+    /// Latency of each stage (seconds), see [`Timings`]
+    pub stage_seconds: BTreeMap<&'static str, Histogram>,
+    /// Whisper time over audio length
+    pub real_time_factor: Histogram,
+}
+
+impl Snapshot {
+    fn new() -> Self {
+        Self {
+            uptime_seconds: 0.0,
+            state: None,
+            utterances: Outcome::ALL.iter().map(|outcome| (outcome.name(), 0)).collect(),
+            errors: ErrorKind::ALL.iter().map(|kind| (kind.name(), 0)).collect(),
+            audio_seconds: 0.0,
+            state_seconds: AppState::ALL.iter().map(|state| (state.name(), 0.0)).collect(),
+            stage_seconds: STAGES
+                .iter()
+                .map(|&stage| (stage, Histogram::new(LATENCY_BUCKETS)))
+                .collect(),
+            real_time_factor: Histogram::new(RTF_BUCKETS),
+        }
+    }
+
+    /// Render in the OpenMetrics text format, which Prometheus scrapes
+    pub fn to_openmetrics(&self) -> String {
+        let mut out = String::new();
+
+        metric_header(&mut out, "thehand_uptime_seconds", "gauge", "Time since TheHand started");
+        let _ = writeln!(out, "thehand_uptime_seconds {}", self.uptime_seconds);
+
+        metric_header(&mut out, "thehand_utterances", "counter", "Finished utterances by outcome");
+        for (outcome, count) in &self.utterances {
+            let _ = writeln!(out, "thehand_utterances_total{{outcome=\"{}\"}} {}", outcome, count);
+        }
+
+        metric_header(&mut out, "thehand_errors", "counter", "Errors by kind");
+        for (kind, count) in &self.errors {
+            let _ = writeln!(out, "thehand_errors_total{{kind=\"{}\"}} {}", kind, count);
+        }
+
+        metric_header(&mut out, "thehand_audio_seconds", "counter", "Audio recorded and transcribed");
+        let _ = writeln!(out, "thehand_audio_seconds_total {}", self.audio_seconds);
+
+        metric_header(&mut out, "thehand_state_seconds", "counter", "Time spent in each state");
+        for (state, seconds) in &self.state_seconds {
+            let _ = writeln!(out, "thehand_state_seconds_total{{state=\"{}\"}} {}", state, seconds);
+        }
+
+        metric_header(&mut out, "thehand_state", "stateset", "Current state");
+        if let Some(current) = self.state {
+            for state in self.state_seconds.keys() {
+                let _ = writeln!(
+                    out,
+                    "thehand_state{{thehand_state=\"{}\"}} {}",
+                    state,
+                    u8::from(*state == current)
+                );
+            }
+        }
+
+        metric_header(&mut out, "thehand_stage_seconds", "histogram", "Latency of each utterance stage");
+        for (stage, histogram) in &self.stage_seconds {
+            histogram_lines(&mut out, "thehand_stage_seconds", &format!("stage=\"{}\",", stage), histogram);
+        }
+
+        metric_header(&mut out, "thehand_real_time_factor", "histogram", "Whisper time over audio length");
+        histogram_lines(&mut out, "thehand_real_time_factor", "", &self.real_time_factor);
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Stages timed for every utterance, as histogram labels
+const STAGES: [&str; 8] = [
+    "silence_wait",
+    "wav_write",
+    "queued",
+    "wake",
+    "transcription",
+    "postprocess",
+    "typing",
+    "total",
+];
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}.", name, help);
+}
+
+/// Bucket, count and sum lines; `labels` is empty or ends with a comma
+fn histogram_lines(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    for (bound, count) in histogram.cumulative() {
+        let _ = writeln!(out, "{}_bucket{{{}le=\"{:?}\"}} {}", name, labels, bound, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, histogram.count);
+    let labels = labels.trim_end_matches(',');
+    let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+    let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+}
+
+/// Counters for a long-running pipeline, shared with the metrics endpoint
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug)]
+struct Registry {
+    started: Instant,
+    /// Current front end state and when it was entered
+    state: Option<(AppState, Instant)>,
+    counts: Snapshot,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            registry: Arc::new(Mutex::new(Registry {
+                started: Instant::now(),
+                state: None,
+                counts: Snapshot::new(),
+            })),
+        }
+    }
+
+    /// Count a finished transcription and its stage timings
+    pub fn record_transcription(&self, record: &HistoryRecord) {
+        let outcome = if record.rejected.is_some() {
+            Outcome::Rejected
+        } else if record.typed {
+            Outcome::Typed
+        } else {
+            Outcome::NotTyped
+        };
+        self.record_outcome(outcome);
+
+        let Ok(mut registry) = self.registry.lock() else { return };
+        let counts = &mut registry.counts;
+        counts.audio_seconds += f64::from(record.duration);
+
+        let timings = &record.timings;
+        let mut stages = vec![
+            ("silence_wait", timings.silence_wait),
+            ("wav_write", timings.wav_write),
+            ("queued", timings.queued),
+            ("transcription", timings.transcription),
+            ("postprocess", timings.postprocess),
+            ("total", timings.total()),
+        ];
+        if timings.wake > 0.0 {
+            stages.push(("wake", timings.wake));
+        }
+        if record.typed {
+            stages.push(("typing", timings.typing));
+        }
+        for (stage, seconds) in stages {
+            if let Some(histogram) = counts.stage_seconds.get_mut(stage) {
+                histogram.observe(f64::from(seconds));
+            }
+        }
+        counts.real_time_factor.observe(f64::from(timings.real_time_factor));
+    }
+
+    pub fn record_outcome(&self, outcome: Outcome) {
+        if let Ok(mut registry) = self.registry.lock() {
+            *registry.counts.utterances.entry(outcome.name()).or_default() += 1;
+        }
+    }
+
+    pub fn record_error(&self, kind: ErrorKind) {
+        if let Ok(mut registry) = self.registry.lock() {
+            *registry.counts.errors.entry(kind.name()).or_default() += 1;
+        }
+    }
+
+    /// Note the front end's current state, to track time spent in each
+    ///
+    /// Call it whenever the state may have changed; calling it more often is harmless.
+    pub fn enter_state(&self, state: AppState) {
+        let Ok(mut registry) = self.registry.lock() else { return };
+        if registry.state.is_some_and(|(current, _)| current == state) {
+            return;
+        }
+        let now = Instant::now();
+        if let Some((previous, since)) = registry.state.replace((state, now)) {
+            *registry.counts.state_seconds.entry(previous.name()).or_default() +=
+                (now - since).as_secs_f64();
+        }
+    }
+
+    /// Everything counted so far
+    pub fn snapshot(&self) -> Snapshot {
+        let Ok(registry) = self.registry.lock() else { return Snapshot::new() };
+        let mut snapshot = registry.counts.clone();
+        snapshot.uptime_seconds = registry.started.elapsed().as_secs_f64();
+
+        // Include the time so far in the current state
+        if let Some((state, since)) = registry.state {
+            snapshot.state = Some(state.name());
+            *snapshot.state_seconds.entry(state.name()).or_default() +=
+                since.elapsed().as_secs_f64();
+        }
+
+        for histogram in snapshot.stage_seconds.values_mut() {
+            histogram.finish();
+        }
+        snapshot.real_time_factor.finish();
+        snapshot
+    }
+}
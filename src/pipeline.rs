@@ -0,0 +1,857 @@
+use crate::archive::Archive;
+use crate::audio::{AudioCapture, AudioEvent, Recording};
+use crate::config::Config;
+use crate::filter::{HallucinationFilter, Verdict};
+use crate::history::{HistoryRecord, HistoryStore};
+use crate::exporter::Exporter;
+use crate::metrics::{ErrorKind, Metrics, Outcome, Timings};
+use crate::postprocess::PostProcessor;
+use crate::runtime;
+use crate::source::AudioSource;
+use crate::streaming::{self, Streamer};
+use crate::transcribe::{self, TranscribeError, Transcriber, Transcription, TranscriptionJob};
+use crate::typing::{KeyboardSink, OutputSink};
+use crate::wake::Spotter;
+use anyhow::Result;
+use chrono::Local;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, warn, Span};
+
+/// Something that happened in the pipeline, for a front end to show
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    /// The transcriber is still loading
+    ModelLoading { elapsed: Duration },
+    /// The transcriber is ready
+    ModelLoaded,
+    /// The transcriber couldn't be loaded; speech is ignored from now on
+    ModelFailed(String),
+    /// Audio level update (RMS and absolute sample peak, 0.0-1.0)
+    Level { rms: f32, peak: f32 },
+    /// Voice activity detected
+    VoiceDetected,
+    /// Recording started
+    RecordingStarted,
+    /// Silence detected while recording
+    SilenceDetected,
+    /// Recording finished and queued for transcription
+    RecordingStopped { duration: f32, clipped_samples: usize },
+    /// Recording thrown away before transcription
+    RecordingCancelled,
+    /// Speech that didn't start with the wake phrase, thrown away
+    Ignored,
+    /// Live hypothesis while streaming
+    Partial { committed: String, partial: String },
+    /// Accepted text about to be typed
+    Typing(String),
+    /// Transcription finished, typed or rejected, and saved to the history
+    Transcribed(HistoryRecord),
+    /// Capture or transcription failed; the pipeline is waiting for speech again
+    Failed(String),
+    /// Something went wrong that didn't interrupt the pipeline
+    Error(String),
+    /// The input device failed; capture resumes once it can be reopened
+    DeviceLost,
+    /// Capture resumed on the named input device
+    DeviceRestored(String),
+    /// Voice activation was muted (true) or resumed (false)
+    Muted(bool),
+}
+
+/// Configures and starts a [`Pipeline`]
+pub struct PipelineBuilder {
+    config: Config,
+    source: Option<Box<dyn AudioSource>>,
+    sink: Option<Box<dyn OutputSink>>,
+}
+
+impl PipelineBuilder {
+    /// Take audio from somewhere other than the default input device
+    pub fn source(mut self, source: impl AudioSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Like [`PipelineBuilder::source`], for an already boxed source
+    pub fn boxed_source(mut self, source: Box<dyn AudioSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Deliver text somewhere other than the focused window
+    pub fn sink(mut self, sink: impl OutputSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Open the history and archive, start capturing and begin loading the model
+    pub fn build(self) -> Result<Pipeline> {
+        let config = self.config;
+        let mut events = Vec::new();
+        let metrics = Metrics::new();
+
+        // Recordings waiting for whisper live in a private directory; clear out
+        // whatever a crashed run left behind
+        let wav_dir = if config.whisper.backend.needs_wav() {
+            let dir = runtime::dir()?;
+            runtime::remove_stale(&dir)?;
+            Some(dir)
+        } else {
+            None
+        };
+
+        let history = config
+            .history
+            .enabled
+            .then(|| HistoryStore::open(&config.history.path));
+        let archive = config
+            .audio
+            .keep_recordings
+            .then(|| Archive::new(&config.audio));
+        if let Some(ref archive) = archive {
+            if let Err(e) = archive.prune() {
+                warn!("Failed to prune recordings: {:#}", e);
+                metrics.record_error(ErrorKind::Archive);
+                events.push(PipelineEvent::Error(format!("Failed to prune recordings: {}", e)));
+            }
+        }
+
+        let filter = HallucinationFilter::new(&config.filter);
+        let postprocessor = PostProcessor::new(&config.postprocess)?;
+        let sink = self
+            .sink
+            .unwrap_or_else(|| Box::new(KeyboardSink::new(config.typing.keystroke_delay)));
+
+        // Load the transcriber in the background; the in-process model can take a while
+        let loader = {
+            let whisper = config.whisper.clone();
+            let wake = config.wake.clone();
+            thread::spawn(move || {
+                let transcriber = Transcriber::load(&whisper)?;
+                let spotter = if wake.enabled {
+                    Some(Spotter::load(&transcriber, &whisper, &wake)?)
+                } else {
+                    None
+                };
+                Ok((transcriber, spotter))
+            })
+        };
+
+        let audio = match self.source {
+            Some(source) => AudioCapture::with_source(source, &config.audio, wav_dir.clone())?,
+            None => AudioCapture::new(&config.audio, wav_dir.clone())?,
+        };
+        let streamer = Streamer::new(&config.streaming, config.audio.sample_rate, wav_dir.clone());
+        info!(
+            backend = ?config.whisper.backend,
+            model = %config.whisper.model_path,
+            sample_rate = config.audio.sample_rate,
+            "Pipeline started"
+        );
+
+        // Serving metrics is optional, so failing to listen doesn't stop dictation
+        let exporter = if config.metrics.enabled {
+            match Exporter::start(&config.metrics.address, metrics.clone()) {
+                Ok(exporter) => Some(exporter),
+                Err(e) => {
+                    events.push(PipelineEvent::Error(format!("{:#}", e)));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Pipeline {
+            config,
+            audio,
+            streamer,
+            loader: Some(loader),
+            load_started: Instant::now(),
+            transcriber: None,
+            spotter: None,
+            wav_dir,
+            queued: VecDeque::new(),
+            active_job: None,
+            filter,
+            postprocessor,
+            sink,
+            history,
+            archive,
+            muted: false,
+            load_failed: false,
+            events,
+            metrics,
+            exporter,
+            recording_span: None,
+            next_utterance: 1,
+        })
+    }
+}
+
+/// The transcriber, and the wake phrase check if it is on
+type Loaded = (Transcriber, Option<Spotter>);
+
+/// A recording waiting for, or undergoing, its final transcription
+struct Utterance {
+    recording: Recording,
+    /// Words already typed live while streaming
+    typed_live: String,
+    /// When the recording finished, for latency tracking
+    stopped_at: Instant,
+    /// When whisper started on it
+    started_at: Option<Instant>,
+    /// Whether the wake phrase has been heard at its start
+    woken: bool,
+    /// Its opening, while being checked for the wake phrase
+    opening: Option<Recording>,
+    timings: Timings,
+    /// Diagnostic log span covering everything about this utterance
+    span: Span,
+}
+
+impl Utterance {
+    /// Remove the recording's temporary WAV files
+    fn discard(&self) {
+        self.recording.discard();
+        if let Some(ref opening) = self.opening {
+            opening.discard();
+        }
+    }
+}
+
+/// Capture, detect speech, transcribe, filter, post-process and type
+///
+/// Call [`Pipeline::poll`] regularly; it does whatever work is due without
+/// blocking on whisper and returns what happened since the last call.
+pub struct Pipeline {
+    config: Config,
+    audio: AudioCapture,
+    streamer: Streamer,
+    loader: Option<JoinHandle<Result<Loaded>>>,
+    load_started: Instant,
+    transcriber: Option<Transcriber>,
+    /// The transcriber couldn't be loaded, so speech is ignored
+    load_failed: bool,
+    /// Checks for the wake phrase, if it is required
+    spotter: Option<Spotter>,
+    /// Where recordings are saved for whisper, if the backend reads from disk
+    wav_dir: Option<PathBuf>,
+    queued: VecDeque<Utterance>,
+    active_job: Option<(Utterance, TranscriptionJob)>,
+    filter: HallucinationFilter,
+    postprocessor: PostProcessor,
+    sink: Box<dyn OutputSink>,
+    history: Option<HistoryStore>,
+    archive: Option<Archive>,
+    /// Recordings are thrown away while muted
+    muted: bool,
+    /// Events not yet returned by `poll`
+    events: Vec<PipelineEvent>,
+    metrics: Metrics,
+    exporter: Option<Exporter>,
+    /// Log span of the recording in progress
+    recording_span: Option<Span>,
+    /// Number of the next utterance, to tell them apart in the log
+    next_utterance: u64,
+}
+
+impl Pipeline {
+    pub fn builder(config: Config) -> PipelineBuilder {
+        PipelineBuilder {
+            config,
+            source: None,
+            sink: None,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Utterance, error and latency counters, as served by the metrics endpoint
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Where the metrics endpoint is listening, if it is enabled
+    pub fn metrics_address(&self) -> Option<SocketAddr> {
+        self.exporter.as_ref().map(Exporter::address)
+    }
+
+    /// The history store, unless the history is disabled
+    pub fn history(&self) -> Option<&HistoryStore> {
+        self.history.as_ref()
+    }
+
+    /// Whether a file or pipe source has delivered all its audio
+    pub fn source_finished(&self) -> bool {
+        self.audio.is_finished()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Stop or resume acting on speech
+    ///
+    /// A transcription already running still finishes and is typed.
+    pub fn set_muted(&mut self, muted: bool) {
+        if muted != self.muted {
+            info!(muted, "Voice activation {}", if muted { "muted" } else { "resumed" });
+            self.muted = muted;
+            self.events.push(PipelineEvent::Muted(muted));
+        }
+    }
+
+    /// Type text through the pipeline's sink, e.g. to re-type a history entry
+    pub fn type_text(&mut self, text: &str) -> Result<()> {
+        self.sink.type_text(text)
+    }
+
+    /// Abandon the recording in progress, or else the running transcription
+    pub fn cancel(&mut self) {
+        if self.audio.is_recording() {
+            self.audio.cancel_recording();
+            self.streamer.stop();
+            if let Some(span) = self.recording_span.take() {
+                span.in_scope(|| info!("Recording cancelled"));
+            }
+            self.metrics.record_outcome(Outcome::Cancelled);
+            self.events.push(PipelineEvent::RecordingCancelled);
+        } else if let Some((utterance, mut job)) = self.active_job.take() {
+            utterance.span.in_scope(|| info!("Transcription cancelled"));
+            self.metrics.record_outcome(Outcome::Cancelled);
+            self.events.push(PipelineEvent::Failed(job.cancel().to_string()));
+            utterance.discard();
+        }
+    }
+
+    /// Do whatever work is due and return what happened
+    ///
+    /// If the transcriber can't be loaded, that is reported as
+    /// [`PipelineEvent::ModelFailed`] and speech is ignored from then on.
+    pub fn poll(&mut self) -> Vec<PipelineEvent> {
+        self.poll_audio();
+        self.poll_loader();
+        self.poll_streaming();
+        self.start_next();
+        self.poll_job();
+        self.poll_archive();
+        std::mem::take(&mut self.events)
+    }
+
+    /// Whether speech should be recorded and transcribed
+    fn listening(&self) -> bool {
+        !self.muted && !self.load_failed
+    }
+
+    fn poll_audio(&mut self) {
+        self.audio.supervise();
+        while let Some(event) = self.audio.poll_event() {
+            match event {
+                AudioEvent::Level { rms, peak } => {
+                    if !self.muted {
+                        self.events.push(PipelineEvent::Level { rms, peak });
+                    }
+                }
+                AudioEvent::VoiceDetected => {
+                    if self.listening() {
+                        self.events.push(PipelineEvent::VoiceDetected);
+                    }
+                }
+                AudioEvent::RecordingStarted => {
+                    if self.listening() {
+                        let span = info_span!("utterance", id = self.next_utterance);
+                        self.next_utterance += 1;
+                        span.in_scope(|| debug!("Recording started"));
+                        self.recording_span = Some(span);
+                        self.streamer.start();
+                        self.events.push(PipelineEvent::RecordingStarted);
+                    }
+                }
+                AudioEvent::RecordingStopped(recording) => {
+                    let typed_live = self.streamer.stop();
+                    let span = self.recording_span.take().unwrap_or_else(Span::none);
+                    if self.listening() {
+                        span.in_scope(|| {
+                            info!(
+                                duration = recording.duration(),
+                                clipped = recording.clipped_samples,
+                                wav_write = recording.wav_write,
+                                "Recording stopped"
+                            )
+                        });
+                        self.events.push(PipelineEvent::RecordingStopped {
+                            duration: recording.duration(),
+                            clipped_samples: recording.clipped_samples,
+                        });
+                        let timings = Timings {
+                            silence_wait: recording.silence_wait,
+                            wav_write: recording.wav_write,
+                            ..Timings::default()
+                        };
+                        self.queued.push_back(Utterance {
+                            recording,
+                            typed_live,
+                            stopped_at: Instant::now(),
+                            started_at: None,
+                            woken: false,
+                            opening: None,
+                            timings,
+                            span,
+                        });
+                    } else {
+                        recording.discard();
+                    }
+                }
+                AudioEvent::SilenceDetected => {
+                    self.events.push(PipelineEvent::SilenceDetected);
+                }
+                AudioEvent::Error(msg) => self.fail(ErrorKind::Capture, msg),
+                AudioEvent::DeviceLost => {
+                    self.drop_recording();
+                    warn!("Input device lost; reopening it when possible");
+                    self.events.push(PipelineEvent::DeviceLost);
+                }
+                AudioEvent::DeviceRestored(name) => {
+                    info!(device = %name, "Input device reopened");
+                    self.events.push(PipelineEvent::DeviceRestored(name));
+                }
+                AudioEvent::DeviceChanged(name) => {
+                    // The capture already cancelled any recording on the old device
+                    self.drop_recording();
+                    info!(device = %name, "Switched to the new default input device");
+                }
+            }
+        }
+    }
+
+    /// Pick up the transcriber once it has loaded
+    fn poll_loader(&mut self) {
+        if self.loader.as_ref().is_some_and(|handle| handle.is_finished()) {
+            let loaded = self
+                .loader
+                .take()
+                .and_then(|handle| handle.join().ok())
+                .unwrap_or_else(|| Err(anyhow::anyhow!("Model loader thread panicked")));
+            match loaded {
+                Ok((transcriber, spotter)) => {
+                    self.transcriber = Some(transcriber);
+                    self.spotter = spotter;
+                    info!(elapsed = ?self.load_started.elapsed(), "Model loaded");
+                    self.events.push(PipelineEvent::ModelLoaded);
+                }
+                Err(e) => {
+                    let message = format!("Failed to load the model: {:#}", e);
+                    error!(kind = ErrorKind::Transcription.name(), "{}", message);
+                    self.metrics.record_error(ErrorKind::Transcription);
+                    self.events.push(PipelineEvent::ModelFailed(message));
+
+                    // Nothing queued can ever be transcribed
+                    self.load_failed = true;
+                    for utterance in self.queued.drain(..) {
+                        utterance.discard();
+                    }
+                }
+            }
+        } else if self.loader.is_some() {
+            self.events.push(PipelineEvent::ModelLoading {
+                elapsed: self.load_started.elapsed(),
+            });
+        }
+    }
+
+    /// Report the live hypothesis, typing committed words if configured to
+    fn poll_streaming(&mut self) {
+        let Some(ref transcriber) = self.transcriber else { return };
+        let Some(session) = self.streamer.tick(&self.audio, transcriber) else { return };
+
+        self.events.push(PipelineEvent::Partial {
+            committed: session.committed_text(),
+            partial: session.partial_text(),
+        });
+
+        if self.config.streaming.type_incrementally {
+            if let Some(words) = session.take_untyped() {
+                let text = format!("{} ", words);
+                if let Err(e) = self.sink.type_text(&text) {
+                    self.error(ErrorKind::Typing, format!("Failed to type text: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Start the next transcription once whisper is free
+    fn start_next(&mut self) {
+        let (None, Some(transcriber)) = (&self.active_job, &self.transcriber) else { return };
+        let Some(mut utterance) = self.queued.pop_front() else { return };
+
+        let span = utterance.span.clone();
+        let _entered = span.enter();
+        // Back in the queue after the wake phrase was heard, it has waited already
+        if !utterance.woken {
+            utterance.timings.queued = utterance.stopped_at.elapsed().as_secs_f32();
+        }
+        utterance.started_at = Some(Instant::now());
+
+        // Check the opening for the wake phrase before transcribing it all
+        if let (false, Some(spotter)) = (utterance.woken, &self.spotter) {
+            debug!(queued = utterance.timings.queued, "Listening for the wake phrase");
+            match spotter.start(&utterance.recording, self.wav_dir.as_deref()) {
+                Ok((opening, job)) => {
+                    utterance.opening = Some(opening);
+                    self.active_job = Some((utterance, job));
+                }
+                Err(e) => {
+                    self.metrics.record_outcome(Outcome::Failed);
+                    self.fail(ErrorKind::Transcription, TranscribeError::from(e).to_string());
+                    utterance.recording.discard();
+                }
+            }
+            return;
+        }
+
+        debug!(queued = utterance.timings.queued, "Transcribing");
+        match transcriber.start(&utterance.recording) {
+            Ok(job) => self.active_job = Some((utterance, job)),
+            Err(e) => {
+                self.metrics.record_outcome(Outcome::Failed);
+                self.fail(ErrorKind::Transcription, TranscribeError::from(e).to_string());
+                utterance.recording.discard();
+            }
+        }
+    }
+
+    /// Handle the transcription once whisper finishes
+    fn poll_job(&mut self) {
+        let Some(result) = self.active_job.as_mut().and_then(|(_, job)| job.poll()) else {
+            return;
+        };
+        let Some((mut utterance, _job)) = self.active_job.take() else { return };
+        let span = utterance.span.clone();
+        let _entered = span.enter();
+
+        if let Some(opening) = utterance.opening.take() {
+            opening.discard();
+            if let Some(started_at) = utterance.started_at {
+                utterance.timings.wake = started_at.elapsed().as_secs_f32();
+            }
+            self.handle_wake_check(utterance, result);
+            return;
+        }
+        self.finish(utterance, result);
+    }
+
+    /// Type and record a finished transcription of the whole utterance
+    fn finish(&mut self, mut utterance: Utterance, result: Result<Transcription, TranscribeError>) {
+        if let Some(started_at) = utterance.started_at {
+            let transcription = started_at.elapsed().as_secs_f32();
+            utterance.timings.transcription = transcription;
+            utterance.timings.real_time_factor =
+                transcription / utterance.recording.duration().max(f32::EPSILON);
+        }
+
+        if let Some(record) = self.handle_transcription(&utterance, result) {
+            let record = self.save_record(&utterance.recording, record);
+            self.metrics.record_transcription(&record);
+            self.events.push(PipelineEvent::Transcribed(record));
+        }
+        utterance.recording.discard();
+    }
+
+    /// Queue the full transcription if the opening had the wake phrase, else drop it
+    fn handle_wake_check(&mut self, mut utterance: Utterance, result: Result<Transcription, TranscribeError>) {
+        let transcription = match result {
+            Ok(transcription) => Some(transcription),
+            // Noise or a cough with no words in it
+            Err(TranscribeError::Empty) => None,
+            Err(e) => {
+                let kind = match e {
+                    TranscribeError::Timeout(_) => ErrorKind::Timeout,
+                    _ => ErrorKind::Transcription,
+                };
+                self.metrics.record_outcome(Outcome::Failed);
+                self.fail(kind, e.to_string());
+                utterance.recording.discard();
+                return;
+            }
+        };
+        let Some(spotter) = self.spotter.as_ref() else {
+            utterance.recording.discard();
+            return;
+        };
+        let heard = transcription
+            .as_ref()
+            .is_some_and(|transcription| spotter.phrase().strip(&transcription.text).is_some());
+
+        if heard {
+            debug!(wake = utterance.timings.wake, "Wake phrase heard");
+            utterance.woken = true;
+            match transcription.filter(|_| spotter.covers(&utterance.recording)) {
+                // Heard whole already, so whisper needn't run over it again
+                Some(transcription) => {
+                    utterance.timings.wake = 0.0;
+                    self.finish(utterance, Ok(transcription));
+                }
+                None => self.queued.push_front(utterance),
+            }
+        } else {
+            info!(wake = utterance.timings.wake, "No wake phrase; ignored");
+            self.metrics.record_outcome(Outcome::Ignored);
+            self.events.push(PipelineEvent::Ignored);
+            utterance.recording.discard();
+        }
+    }
+
+    /// Filter, post-process and type a finished transcription
+    ///
+    /// Returns the history record for it, unless whisper failed before
+    /// anything was typed.
+    fn handle_transcription(
+        &mut self,
+        utterance: &Utterance,
+        result: Result<Transcription, TranscribeError>,
+    ) -> Option<HistoryRecord> {
+        let mut transcription = match result {
+            Ok(transcription) => transcription,
+            Err(e) => {
+                let kind = match e {
+                    TranscribeError::Timeout(_) => ErrorKind::Timeout,
+                    _ => ErrorKind::Transcription,
+                };
+                // Words typed live are in the window whatever became of the rest
+                if !utterance.typed_live.is_empty() {
+                    self.error(kind, format!("{}; kept the words typed while streaming", e));
+                    let mut record = self.new_record(utterance, utterance.typed_live.clone());
+                    record.window = self.sink.focused_window();
+                    record.typed = true;
+                    record.latency = utterance.stopped_at.elapsed().as_secs_f32();
+                    return Some(record);
+                }
+                self.metrics.record_outcome(Outcome::Failed);
+                self.fail(kind, e.to_string());
+                return None;
+            }
+        };
+
+        // The wake phrase is for TheHand, not for typing
+        let raw_text = transcription.text.clone();
+        let mut phrase_missing = false;
+        if let Some(ref spotter) = self.spotter {
+            match spotter.phrase().strip(&transcription.text) {
+                Some(rest) => transcription.text = rest,
+                // Heard in the opening only; typing it all would type the phrase
+                None => phrase_missing = true,
+            }
+        }
+
+        let mut record = HistoryRecord {
+            text: transcription.text.clone(),
+            language: transcription.language.clone(),
+            confidence: transcription.confidence(),
+            ..self.new_record(utterance, raw_text)
+        };
+
+        info!(
+            transcription = record.timings.transcription,
+            real_time_factor = record.timings.real_time_factor,
+            language = record.language.as_deref().unwrap_or("?"),
+            confidence = record.confidence.unwrap_or(f32::NAN),
+            "Transcribed"
+        );
+
+        let postprocess_started = Instant::now();
+        let verdict = if phrase_missing {
+            Verdict::Rejected("wake phrase not in the full transcription".to_string())
+        } else {
+            self.filter.check(&transcription, &utterance.recording)
+        };
+        match verdict {
+            Verdict::Accepted(cleaned) => {
+                let mut text = self.postprocessor.apply(&cleaned);
+
+                // Words typed live while streaming stand; only what follows them is typed
+                let mut to_type = text.clone();
+                if !utterance.typed_live.is_empty() {
+                    match streaming::continuation(&text, &utterance.typed_live) {
+                        Some(rest) => {
+                            text = format!("{} {}", utterance.typed_live, rest).trim_end().to_string();
+                            to_type = rest;
+                        }
+                        None => {
+                            self.error(
+                                ErrorKind::Transcription,
+                                "The final transcription differs from the words typed while streaming, so the rest wasn't typed"
+                                    .to_string(),
+                            );
+                            text = utterance.typed_live.clone();
+                            to_type.clear();
+                        }
+                    }
+                }
+                self.events.push(PipelineEvent::Typing(text.clone()));
+
+                record.timings.postprocess = postprocess_started.elapsed().as_secs_f32();
+
+                // Type the text
+                record.window = self.sink.focused_window();
+                let typing_started = Instant::now();
+                let typed = if to_type.is_empty() {
+                    Ok(())
+                } else {
+                    self.sink.type_text(&to_type)
+                };
+                record.timings.typing = typing_started.elapsed().as_secs_f32();
+                if let Err(e) = typed {
+                    self.error(ErrorKind::Typing, format!("Failed to type text: {}", e));
+                } else {
+                    record.typed = true;
+                    debug!(
+                        chars = to_type.chars().count(),
+                        typing = record.timings.typing,
+                        window = record.window.as_deref().unwrap_or("?"),
+                        "Typed"
+                    );
+
+                    // Keep the transcript, apart from the diagnostic log
+                    if self.config.ui.log_to_file {
+                        if let Err(e) = log_transcription(&self.config.ui.log_path, &text, &record.timings) {
+                            warn!("Failed to write the transcript log: {:#}", e);
+                        }
+                    }
+                }
+
+                record.text = text;
+            }
+            Verdict::Rejected(reason) => {
+                info!(%reason, "Rejected");
+                record.timings.postprocess = postprocess_started.elapsed().as_secs_f32();
+                record.rejected = Some(reason);
+            }
+        }
+
+        record.latency = utterance.stopped_at.elapsed().as_secs_f32();
+        debug!(latency = record.latency, timings = %record.timings, "Finished");
+        Some(record)
+    }
+
+    /// A history record of `text` for `utterance`, not yet typed
+    fn new_record(&self, utterance: &Utterance, text: String) -> HistoryRecord {
+        HistoryRecord {
+            timestamp: Local::now(),
+            text: text.clone(),
+            raw_text: text,
+            duration: utterance.recording.duration(),
+            latency: 0.0,
+            peak: utterance.recording.peak,
+            clipped_samples: utterance.recording.clipped_samples,
+            model: transcribe::model_name(&self.config.whisper.model_path),
+            language: None,
+            confidence: None,
+            window: None,
+            typed: false,
+            rejected: None,
+            audio: None,
+            timings: utterance.timings,
+        }
+    }
+
+    /// Keep the recording and save the record to the history
+    fn save_record(&mut self, recording: &Recording, mut record: HistoryRecord) -> HistoryRecord {
+        let kept = self
+            .archive
+            .as_ref()
+            .map(|archive| archive.save(recording, &record.timestamp));
+        match kept {
+            Some(Ok(path)) => record.audio = Some(path),
+            Some(Err(e)) => self.error(ErrorKind::Archive, format!("Failed to keep recording: {}", e)),
+            None => {}
+        }
+
+        let saved = self.history.as_ref().map(|store| store.append(&record));
+        if let Some(Err(e)) = saved {
+            self.error(ErrorKind::History, format!("Failed to save history: {}", e));
+        }
+
+        record
+    }
+
+    /// Report recordings the archive failed to encode or prune after saving
+    fn poll_archive(&mut self) {
+        while let Some(e) = self.archive.as_ref().and_then(Archive::take_error) {
+            self.error(ErrorKind::Archive, format!("Failed to keep recording: {}", e));
+        }
+    }
+
+    /// Forget a recording the capture abandoned when its device went away
+    fn drop_recording(&mut self) {
+        if let Some(span) = self.recording_span.take() {
+            self.streamer.stop();
+            span.in_scope(|| info!("Recording abandoned with its input device"));
+        }
+    }
+
+    /// Report a failure that ended the current utterance
+    fn fail(&mut self, kind: ErrorKind, message: String) {
+        error!(kind = kind.name(), "{}", message);
+        self.metrics.record_error(kind);
+        self.events.push(PipelineEvent::Failed(message));
+    }
+
+    /// Report a problem that didn't interrupt the pipeline
+    fn error(&mut self, kind: ErrorKind, message: String) {
+        warn!(kind = kind.name(), "{}", message);
+        self.metrics.record_error(kind);
+        self.events.push(PipelineEvent::Error(message));
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        // Dropping running jobs kills whisper's process group before its input goes
+        if let Some((utterance, job)) = self.active_job.take() {
+            drop(job);
+            utterance.discard();
+        }
+        for utterance in self.queued.drain(..) {
+            utterance.recording.discard();
+        }
+        self.streamer.stop();
+    }
+}
+
+/// Transcript log size at which it is moved aside to `<log_path>.1`
+const TRANSCRIPT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+fn log_transcription(log_path: &str, text: &str, timings: &Timings) -> Result<()> {
+    let path = PathBuf::from(shellexpand::tilde(log_path).to_string());
+
+    // Create parent directory if needed
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Start a new file once it grows large, keeping only the previous one
+    if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= TRANSCRIPT_LOG_MAX_BYTES) {
+        let mut previous = path.clone().into_os_string();
+        previous.push(".1");
+        std::fs::rename(&path, previous)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+    writeln!(file, "[{}] {} ({})", timestamp, text, timings)?;
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+use crate::config::PostProcessStep;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// A compiled post-processing step, ready to apply to a transcription
+enum Step {
+    Replace { from: String, to: String },
+    Regex { regex: Regex, replacement: String },
+    Dictionary(Vec<(Regex, String)>),
+    StripTrailingPeriod,
+    LowercaseFirst,
+    CollapseWhitespace,
+    Append(String),
+    NumberWords,
+}
+
+impl Step {
+    fn compile(step: &PostProcessStep) -> Result<Self> {
+        Ok(match step {
+            PostProcessStep::Replace { from, to } => Step::Replace {
+                from: from.clone(),
+                to: to.clone(),
+            },
+            PostProcessStep::Regex {
+                pattern,
+                replacement,
+            } => Step::Regex {
+                regex: Regex::new(pattern)
+                    .context(format!("Invalid regex in postprocess step: {}", pattern))?,
+                replacement: replacement.clone(),
+            },
+            PostProcessStep::Dictionary { words } => {
+                // Longest phrases first so "git hub actions" wins over "git hub"
+                let mut entries: Vec<_> = words.iter().collect();
+                entries.sort_by_key(|(heard, _)| std::cmp::Reverse(heard.len()));
+
+                let compiled = entries
+                    .into_iter()
+                    .map(|(heard, word)| {
+                        let pattern = format!(r"(?i)\b{}\b", regex::escape(heard));
+                        let regex = Regex::new(&pattern)
+                            .context(format!("Invalid dictionary entry: {}", heard))?;
+                        Ok((regex, word.clone()))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                Step::Dictionary(compiled)
+            }
+            PostProcessStep::StripTrailingPeriod => Step::StripTrailingPeriod,
+            PostProcessStep::LowercaseFirst => Step::LowercaseFirst,
+            PostProcessStep::CollapseWhitespace => Step::CollapseWhitespace,
+            PostProcessStep::Append { text } => Step::Append(text.clone()),
+            PostProcessStep::NumberWords => Step::NumberWords,
+        })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        match self {
+            Step::Replace { from, to } => replace_literal(text, from, to),
+            Step::Regex { regex, replacement } => {
+                regex.replace_all(text, replacement.as_str()).into_owned()
+            }
+            Step::Dictionary(entries) => apply_dictionary(text, entries),
+            Step::StripTrailingPeriod => strip_trailing_period(text),
+            Step::LowercaseFirst => lowercase_first(text),
+            Step::CollapseWhitespace => collapse_whitespace(text),
+            Step::Append(suffix) => format!("{}{}", text, suffix),
+            Step::NumberWords => number_words_to_digits(text),
+        }
+    }
+}
+
+/// Ordered text post-processing pipeline applied to every transcription
+pub struct PostProcessor {
+    steps: Vec<Step>,
+}
+
+impl PostProcessor {
+    /// Compile the configured steps, failing on invalid regexes
+    pub fn new(steps: &[PostProcessStep]) -> Result<Self> {
+        let steps = steps
+            .iter()
+            .map(Step::compile)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { steps })
+    }
+
+    /// Run the text through every step in order
+    pub fn apply(&self, text: &str) -> String {
+        self.steps
+            .iter()
+            .fold(text.to_string(), |acc, step| step.apply(&acc))
+    }
+}
+
+/// Replace every occurrence of `from` with `to`
+pub fn replace_literal(text: &str, from: &str, to: &str) -> String {
+    if from.is_empty() {
+        return text.to_string();
+    }
+    text.replace(from, to)
+}
+
+/// Replace whole-word, case-insensitive matches with their dictionary spelling
+fn apply_dictionary(text: &str, entries: &[(Regex, String)]) -> String {
+    entries.iter().fold(text.to_string(), |acc, (regex, word)| {
+        regex
+            .replace_all(&acc, regex::NoExpand(word.as_str()))
+            .into_owned()
+    })
+}
+
+/// Remove a single trailing period and any whitespace after it, leaving
+/// ellipses and text without one alone
+pub fn strip_trailing_period(text: &str) -> String {
+    let trimmed = text.trim_end();
+    if trimmed.ends_with('.') && !trimmed.ends_with("..") {
+        trimmed[..trimmed.len() - 1].to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Lowercase the first letter of the text
+pub fn lowercase_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Collapse runs of whitespace to a single space and trim both ends
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Value of a single number word, if it is one
+fn number_word_value(word: &str) -> Option<u64> {
+    let value = match word {
+        "zero" => 0,
+        "one" => 1,
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" => 12,
+        "thirteen" => 13,
+        "fourteen" => 14,
+        "fifteen" => 15,
+        "sixteen" => 16,
+        "seventeen" => 17,
+        "eighteen" => 18,
+        "nineteen" => 19,
+        "twenty" => 20,
+        "thirty" => 30,
+        "forty" => 40,
+        "fifty" => 50,
+        "sixty" => 60,
+        "seventy" => 70,
+        "eighty" => 80,
+        "ninety" => 90,
+        "hundred" => 100,
+        "thousand" => 1_000,
+        "million" => 1_000_000,
+        "billion" => 1_000_000_000,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Accumulates a run of number words into a single value
+#[derive(Default)]
+struct NumberRun {
+    total: u64,
+    current: u64,
+    words: usize,
+}
+
+impl NumberRun {
+    /// Add a number word, unless the value would overflow
+    ///
+    /// Whisper loops like "hundred hundred hundred ..." get there quickly.
+    fn push(&mut self, value: u64) -> bool {
+        let next = match value {
+            100 => self
+                .current
+                .max(1)
+                .checked_mul(100)
+                .map(|current| (self.total, current)),
+            v if v >= 1_000 => self
+                .current
+                .max(1)
+                .checked_mul(v)
+                .and_then(|scaled| self.total.checked_add(scaled))
+                .map(|total| (total, 0)),
+            v => self.current.checked_add(v).map(|current| (self.total, current)),
+        };
+        match next {
+            Some((total, current)) if total.checked_add(current).is_some() => {
+                self.total = total;
+                self.current = current;
+                self.words += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `value` continues this number rather than starting a new one
+    fn accepts(&self, value: u64) -> bool {
+        // "zero five" is two digits, not five
+        if self.words > 0 && self.value() == 0 {
+            return false;
+        }
+        let low = self.current % 100;
+        self.words == 0 || value >= 100 || low == 0 || (low >= 20 && low % 10 == 0 && value < 10)
+    }
+
+    fn value(&self) -> u64 {
+        self.total + self.current
+    }
+}
+
+/// Split text into words, each paired with the whitespace that follows it
+fn split_words(text: &str) -> (&str, Vec<(&str, &str)>) {
+    let rest = text.trim_start();
+    let leading = &text[..text.len() - rest.len()];
+    let mut words = Vec::new();
+    let mut rest = rest;
+
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, tail) = rest.split_at(word_end);
+        let next = tail.trim_start();
+        words.push((word, &tail[..tail.len() - next.len()]));
+        rest = next;
+    }
+
+    (leading, words)
+}
+
+/// Strip trailing punctuation and lowercase, returning the bare word
+fn bare_word(token: &str) -> (String, &str) {
+    let word = token.trim_end_matches(|c: char| !c.is_alphanumeric());
+    (word.to_lowercase(), &token[word.len()..])
+}
+
+/// Convert spelled-out numbers to digits ("twenty three" -> "23")
+///
+/// Punctuation attached to the last number word is kept, and a lone "and"
+/// inside a number ("one hundred and five") is absorbed.
+pub fn number_words_to_digits(text: &str) -> String {
+    let (leading, words) = split_words(text);
+    let mut output = String::from(leading);
+    let mut run = NumberRun::default();
+
+    let value_at = |i: usize| {
+        words
+            .get(i)
+            .and_then(|(token, _)| number_word_value(&bare_word(token).0))
+    };
+
+    for (i, &(token, space)) in words.iter().enumerate() {
+        let (word, suffix) = bare_word(token);
+
+        if let Some(value) = number_word_value(&word) {
+            if !run.push(value) {
+                // Too big for one number; what there is so far stands alone
+                output.push_str(&format!("{} ", run.value()));
+                run = NumberRun::default();
+                run.push(value);
+            }
+
+            // "one hundred and five" - an "and" after a round value joins the next number
+            let joined_by_and = run.value() > 0
+                && run.current % 100 == 0
+                && words.get(i + 1).map(|(next, _)| *next) == Some("and");
+            let continues = if joined_by_and {
+                value_at(i + 2).is_some_and(|next| run.accepts(next))
+            } else {
+                value_at(i + 1).is_some_and(|next| run.accepts(next))
+            };
+
+            if !suffix.is_empty() || !continues {
+                output.push_str(&format!("{}{}{}", run.value(), suffix, space));
+                run = NumberRun::default();
+            }
+        } else if word == "and" && run.words > 0 {
+            // Absorbed into the surrounding number
+        } else {
+            output.push_str(token);
+            output.push_str(space);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn apply(step: PostProcessStep, text: &str) -> String {
+        PostProcessor::new(&[step]).unwrap().apply(text)
+    }
+
+    #[test]
+    fn replace_is_literal() {
+        let step = PostProcessStep::Replace {
+            from: "new line".to_string(),
+            to: "\n".to_string(),
+        };
+        assert_eq!(apply(step, "one new line two new line"), "one \n two \n");
+        assert_eq!(replace_literal("a.b", "", "x"), "a.b");
+        assert_eq!(replace_literal("a.b", ".", "$1"), "a$1b");
+    }
+
+    #[test]
+    fn regex_replaces_with_captures() {
+        let step = PostProcessStep::Regex {
+            pattern: r"\s+([,.!?])".to_string(),
+            replacement: "$1".to_string(),
+        };
+        assert_eq!(apply(step, "Hello , world !"), "Hello, world!");
+
+        let invalid = PostProcessStep::Regex {
+            pattern: "(".to_string(),
+            replacement: String::new(),
+        };
+        assert!(PostProcessor::new(&[invalid]).is_err());
+    }
+
+    #[test]
+    fn dictionary_matches_whole_words_longest_first() {
+        let words = BTreeMap::from([
+            ("git hub".to_string(), "GitHub".to_string()),
+            ("git hub actions".to_string(), "GitHub Actions".to_string()),
+            ("rust".to_string(), "Rust".to_string()),
+        ]);
+        let step = PostProcessStep::Dictionary { words };
+        assert_eq!(
+            apply(step, "Git Hub Actions on git hub, trusty rust"),
+            "GitHub Actions on GitHub, trusty Rust"
+        );
+    }
+
+    #[test]
+    fn number_words_become_digits() {
+        assert_eq!(number_words_to_digits("twenty three apples"), "23 apples");
+        assert_eq!(number_words_to_digits("one hundred and five"), "105");
+        assert_eq!(number_words_to_digits("rock and roll"), "rock and roll");
+        assert_eq!(number_words_to_digits("two thousand and twenty four."), "2024.");
+        assert_eq!(number_words_to_digits("zero five"), "0 5");
+        assert_eq!(number_words_to_digits("three four"), "3 4");
+        assert_eq!(number_words_to_digits("  Seven, eight"), "  7, 8");
+    }
+
+    #[test]
+    fn repeated_hundreds_do_not_overflow() {
+        // Nine make 10^18; the tenth would overflow, so it starts a new number
+        let text = ["hundred"; 12].join(" ");
+        assert_eq!(
+            number_words_to_digits(&text),
+            format!("{} {}", 10u64.pow(18), 1_000_000)
+        );
+    }
+
+    #[test]
+    fn trailing_period_is_stripped_but_not_ellipses() {
+        assert_eq!(strip_trailing_period("Done. "), "Done");
+        assert_eq!(strip_trailing_period("Wait..."), "Wait...");
+        assert_eq!(strip_trailing_period("Why?"), "Why?");
+        assert_eq!(strip_trailing_period("Why? "), "Why? ");
+        assert_eq!(strip_trailing_period("Wait... "), "Wait... ");
+    }
+
+    #[test]
+    fn first_letter_is_lowercased() {
+        assert_eq!(lowercase_first("Hello World"), "hello World");
+        assert_eq!(lowercase_first("Émile"), "émile");
+        assert_eq!(lowercase_first(""), "");
+    }
+
+    #[test]
+    fn whitespace_is_collapsed_and_trimmed() {
+        assert_eq!(collapse_whitespace("  a \t b\n\n c "), "a b c");
+    }
+
+    #[test]
+    fn append_adds_text_after_every_other_step() {
+        let steps = [
+            PostProcessStep::StripTrailingPeriod,
+            PostProcessStep::Append { text: " ".to_string() },
+        ];
+        assert_eq!(PostProcessor::new(&steps).unwrap().apply("Done."), "Done ");
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes files created by this process
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// Private per-user directory for recordings waiting to be transcribed
+///
+/// Uses `$XDG_RUNTIME_DIR/thehand`, falling back to `<tmp>/thehand-<uid>`.
+/// The directory is created with mode 0700 and refused if another user owns it.
+pub fn dir() -> Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("thehand"),
+        _ => std::env::temp_dir().join(format!("thehand-{}", uid)),
+    };
+
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).context(format!("Failed to create runtime directory {:?}", dir)),
+    }
+
+    // Don't follow a symlink someone else planted in a shared temp dir
+    let metadata = fs::symlink_metadata(&dir)
+        .context(format!("Failed to inspect runtime directory {:?}", dir))?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        anyhow::bail!("Runtime directory {:?} is not a directory owned by you", dir);
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .context(format!("Failed to make {:?} private", dir))?;
+    }
+
+    Ok(dir)
+}
+
+/// Create a new file in `dir` that no other process can have opened first
+///
+/// Names are `thehand_<pid>_<kind>_<n>.<extension>`, so files left behind by
+/// a crashed process can be recognised by [`remove_stale`].
+pub fn create_file(dir: &Path, kind: &str, extension: &str) -> Result<(PathBuf, File)> {
+    loop {
+        let n = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!(
+            "thehand_{}_{}_{}.{}",
+            std::process::id(),
+            kind,
+            n,
+            extension
+        ));
+
+        // O_EXCL: fails rather than reusing a file that already exists
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).context(format!("Failed to create {:?}", path)),
+        }
+    }
+}
+
+/// Remove files left in `dir` by TheHand processes that are no longer running
+pub fn remove_stale(dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+
+    for entry in fs::read_dir(dir)?.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("thehand_"))
+            .and_then(|rest| rest.split('_').next())
+            .and_then(|pid| pid.parse::<i32>().ok())
+        else {
+            continue;
+        };
+
+        if !process_alive(pid) && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+fn process_alive(pid: i32) -> bool {
+    if pid as u32 == std::process::id() {
+        return true;
+    }
+    // Signal 0 only checks whether the process exists
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
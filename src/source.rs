@@ -0,0 +1,538 @@
+use crate::archive;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Stream, StreamConfig};
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Receives mono f32 samples from a source, one chunk at a time
+pub type SampleCallback = Box<dyn FnMut(&[f32]) + Send + 'static>;
+
+/// Length of the chunks file, pipe and synthetic sources deliver (milliseconds)
+const CHUNK_MS: u32 = 10;
+
+/// Where captured audio comes from
+pub trait AudioSource {
+    /// Sample rate of the delivered samples
+    fn sample_rate(&self) -> u32;
+
+    /// Start delivering samples to `callback`
+    ///
+    /// Delivery continues in the background until the source runs out or is
+    /// dropped.
+    fn start(&mut self, callback: SampleCallback) -> Result<()>;
+
+    /// Whether `callback` may hold up delivery while the capture catches up
+    ///
+    /// A device's realtime callback must never wait, so its samples are
+    /// dropped instead when processing falls behind.
+    fn can_block(&self) -> bool {
+        true
+    }
+
+    /// Whether a finite source has delivered everything it has
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// A stream error reported since the last call, if any
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Whether the source can be stopped and started again, like a device
+    ///
+    /// Only such sources are watched for stalls and reopened after errors.
+    fn can_restart(&self) -> bool {
+        false
+    }
+
+    /// Stop delivering samples, so a restartable source can be started again
+    fn stop(&mut self) {}
+
+    /// Name of the device being captured from, if the source is one
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether starting again would open a different device than the running one
+    fn device_changed(&self) -> bool {
+        false
+    }
+}
+
+/// An input device via cpal, by default whichever the system defaults to
+pub struct CpalSource {
+    sample_rate: u32,
+    /// Part of the wanted device's name; empty to follow the system default
+    device: String,
+    /// Name of the device the stream was opened on
+    opened: Option<String>,
+    stream: Option<Stream>,
+    error_tx: Sender<String>,
+    error_rx: Receiver<String>,
+}
+
+impl CpalSource {
+    pub fn new(sample_rate: u32) -> Self {
+        let (error_tx, error_rx) = channel();
+        Self {
+            sample_rate,
+            device: String::new(),
+            opened: None,
+            stream: None,
+            error_tx,
+            error_rx,
+        }
+    }
+
+    /// Capture from the first device whose name contains `name` (any case)
+    ///
+    /// An empty name follows the system default device.
+    pub fn device(mut self, name: &str) -> Self {
+        self.device = name.to_string();
+        self
+    }
+
+    fn find_device(&self) -> Result<cpal::Device> {
+        let host = cpal::default_host();
+        if self.device.is_empty() {
+            return host.default_input_device().context("No input device available");
+        }
+
+        let wanted = self.device.to_lowercase();
+        let mut names = Vec::new();
+        for device in host.input_devices().context("Failed to list input devices")? {
+            let name = device.name().unwrap_or_default();
+            if name.to_lowercase().contains(&wanted) {
+                return Ok(device);
+            }
+            names.push(name);
+        }
+        anyhow::bail!(
+            "No input device matching {:?} (available: {})",
+            self.device,
+            if names.is_empty() { "none".to_string() } else { names.join(", ") }
+        )
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, mut callback: SampleCallback) -> Result<()> {
+        let device = self.find_device()?;
+
+        // Fails early for a device that can't capture at all
+        device.default_input_config()
+            .context("Failed to get default input config")?;
+
+        let config = StreamConfig {
+            channels: 1,
+            sample_rate: cpal::SampleRate(self.sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        // Reported by the capture's supervisor, which reopens the device
+        let errors = self.error_tx.clone();
+        let err_fn = move |err: cpal::StreamError| {
+            let _ = errors.send(format!("Audio stream error: {}", err));
+        };
+
+        let stream = device.build_input_stream(
+            &config,
+            move |data: &[f32], _: &cpal::InputCallbackInfo| callback(data),
+            err_fn,
+            None,
+        ).context("Failed to build input stream")?;
+
+        stream.play().context("Failed to start audio stream")?;
+        self.opened = Some(device.name().unwrap_or_else(|_| "unknown device".to_string()));
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn can_block(&self) -> bool {
+        false
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error_rx.try_recv().ok()
+    }
+
+    fn can_restart(&self) -> bool {
+        true
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+        self.opened = None;
+        // Errors from the old stream say nothing about the next one
+        while self.error_rx.try_recv().is_ok() {}
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.opened.clone()
+    }
+
+    fn device_changed(&self) -> bool {
+        // A named device stays put; only the default can move
+        if !self.device.is_empty() || self.stream.is_none() {
+            return false;
+        }
+        let default = cpal::default_host()
+            .default_input_device()
+            .and_then(|device| device.name().ok());
+        default.is_some() && default != self.opened
+    }
+}
+
+/// Background thread handing out chunks, stopped when dropped
+struct Feeder {
+    stop: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Feeder {
+    /// Call `callback` with each chunk `next` fills in, until it returns false
+    ///
+    /// In real time, chunks are paced by the sample clock; otherwise they are
+    /// delivered as fast as the callback takes them.
+    fn spawn(
+        sample_rate: u32,
+        realtime: bool,
+        mut next: impl FnMut(&mut Vec<f32>) -> bool + Send + 'static,
+        mut callback: SampleCallback,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        {
+            let stop = stop.clone();
+            let finished = finished.clone();
+            thread::spawn(move || {
+                let started = Instant::now();
+                let mut delivered = 0usize;
+                let mut chunk = Vec::new();
+
+                while !stop.load(Ordering::Relaxed) {
+                    chunk.clear();
+                    let more = next(&mut chunk);
+                    if !chunk.is_empty() {
+                        callback(&chunk);
+                        delivered += chunk.len();
+                    }
+                    if !more {
+                        break;
+                    }
+
+                    if realtime {
+                        let due = Duration::from_secs_f64(delivered as f64 / sample_rate as f64);
+                        if let Some(wait) = due.checked_sub(started.elapsed()) {
+                            thread::sleep(wait);
+                        }
+                    }
+                }
+                // Release: whoever sees the end also sees every delivered chunk
+                finished.store(true, Ordering::Release);
+            });
+        }
+
+        Self { stop, finished }
+    }
+
+    /// Play a buffer of samples in chunks
+    fn play(samples: Vec<f32>, sample_rate: u32, realtime: bool, callback: SampleCallback) -> Self {
+        let chunk_len = chunk_len(sample_rate);
+        let mut position = 0;
+        Self::spawn(
+            sample_rate,
+            realtime,
+            move |chunk| {
+                let end = (position + chunk_len).min(samples.len());
+                chunk.extend_from_slice(&samples[position..end]);
+                position = end;
+                position < samples.len()
+            },
+            callback,
+        )
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Feeder {
+    fn drop(&mut self) {
+        // Not joined: a pipe read can block until the writer goes away
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn chunk_len(sample_rate: u32) -> usize {
+    (sample_rate * CHUNK_MS / 1000).max(1) as usize
+}
+
+/// A WAV, FLAC or Opus file, played once
+pub struct FileSource {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    realtime: bool,
+    feeder: Option<Feeder>,
+}
+
+impl FileSource {
+    /// Decode a file; `realtime` plays it at its natural speed
+    pub fn open(path: &Path, realtime: bool) -> Result<Self> {
+        let (samples, sample_rate) = archive::load(path)?;
+        Ok(Self {
+            samples,
+            sample_rate,
+            realtime,
+            feeder: None,
+        })
+    }
+}
+
+impl AudioSource for FileSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, callback: SampleCallback) -> Result<()> {
+        let samples = std::mem::take(&mut self.samples);
+        self.feeder = Some(Feeder::play(samples, self.sample_rate, self.realtime, callback));
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.feeder.as_ref().is_some_and(Feeder::is_finished)
+    }
+}
+
+/// Sample encoding of raw PCM input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PcmFormat {
+    /// Signed 16-bit little-endian, as `arecord -f S16_LE` or `parec --format=s16le` write
+    #[default]
+    S16le,
+    /// 32-bit float little-endian
+    F32le,
+}
+
+impl PcmFormat {
+    fn sample_bytes(self) -> usize {
+        match self {
+            PcmFormat::S16le => 2,
+            PcmFormat::F32le => 4,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmFormat::S16le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            PcmFormat::F32le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Raw mono PCM read from stdin, a FIFO or any other stream
+///
+/// The stream must already be at the configured sample rate, e.g.
+/// `pw-record --rate 16000 --channels 1 --format s16 - | thehand --input -`.
+pub struct PcmSource {
+    reader: Option<Box<dyn Read + Send>>,
+    format: PcmFormat,
+    sample_rate: u32,
+    feeder: Option<Feeder>,
+    /// A failed read, which ends the stream like the end of input would
+    error_tx: Sender<String>,
+    error_rx: Receiver<String>,
+}
+
+impl PcmSource {
+    pub fn new(reader: impl Read + Send + 'static, format: PcmFormat, sample_rate: u32) -> Self {
+        let (error_tx, error_rx) = channel();
+        Self {
+            reader: Some(Box::new(reader)),
+            format,
+            sample_rate,
+            feeder: None,
+            error_tx,
+            error_rx,
+        }
+    }
+
+    /// Read from stdin
+    pub fn stdin(format: PcmFormat, sample_rate: u32) -> Self {
+        Self::new(std::io::stdin(), format, sample_rate)
+    }
+
+    /// Read from a FIFO or file
+    pub fn open(path: &Path, format: PcmFormat, sample_rate: u32) -> Result<Self> {
+        let file = File::open(path).context(format!("Failed to open {:?}", path))?;
+        Ok(Self::new(file, format, sample_rate))
+    }
+}
+
+impl AudioSource for PcmSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, callback: SampleCallback) -> Result<()> {
+        let mut reader = self.reader.take().context("PCM input can only be started once")?;
+        let format = self.format;
+        let mut bytes = vec![0u8; chunk_len(self.sample_rate) * format.sample_bytes()];
+        let errors = self.error_tx.clone();
+
+        // The writer sets the pace, so no need to sleep between chunks
+        self.feeder = Some(Feeder::spawn(
+            self.sample_rate,
+            false,
+            move |chunk| {
+                let mut filled = 0;
+                while filled < bytes.len() {
+                    match reader.read(&mut bytes[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        // Sent before the stream counts as finished
+                        Err(e) => {
+                            let _ = errors.send(format!("Failed to read audio input: {}", e));
+                            break;
+                        }
+                    }
+                }
+                let whole = filled - filled % format.sample_bytes();
+                chunk.extend(
+                    bytes[..whole]
+                        .chunks_exact(format.sample_bytes())
+                        .map(|sample| format.decode(sample)),
+                );
+                filled == bytes.len()
+            },
+            callback,
+        ));
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.feeder.as_ref().is_some_and(Feeder::is_finished)
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error_rx.try_recv().ok()
+    }
+}
+
+/// One stretch of a synthetic test signal
+#[derive(Debug, Clone, Copy)]
+pub enum Signal {
+    /// Digital silence (seconds)
+    Silence(f32),
+    /// Sine wave
+    Tone { frequency: f32, amplitude: f32, seconds: f32 },
+    /// Uniform white noise
+    Noise { amplitude: f32, seconds: f32 },
+    /// Voiced buzz whose loudness rises and falls like syllables
+    Speech { amplitude: f32, seconds: f32 },
+}
+
+/// Pitch of the synthetic voice (Hz)
+const SPEECH_PITCH: f32 = 150.0;
+/// Syllables per second of the synthetic voice
+const SYLLABLE_RATE: f32 = 4.0;
+
+/// A generated signal, for exercising the pipeline without a sound card
+pub struct SyntheticSource {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    realtime: bool,
+    feeder: Option<Feeder>,
+}
+
+impl SyntheticSource {
+    /// Render the signals back to back; delivered as fast as possible
+    pub fn new(sample_rate: u32, signals: &[Signal]) -> Self {
+        // Fixed-seed xorshift, so runs are repeatable
+        let mut state: u32 = 0x2545_f491;
+        let mut noise = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+
+        let mut samples = Vec::new();
+        for signal in signals {
+            let (seconds, amplitude) = match *signal {
+                Signal::Silence(seconds) => (seconds, 0.0),
+                Signal::Tone { amplitude, seconds, .. }
+                | Signal::Noise { amplitude, seconds }
+                | Signal::Speech { amplitude, seconds } => (seconds, amplitude),
+            };
+            let len = (seconds * sample_rate as f32).round() as usize;
+            samples.extend((0..len).map(|i| match *signal {
+                Signal::Silence(_) => 0.0,
+                Signal::Tone { frequency, .. } => {
+                    amplitude * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin()
+                }
+                Signal::Noise { .. } => amplitude * noise(),
+                Signal::Speech { .. } => {
+                    let t = i as f32 / sample_rate as f32;
+                    let envelope = 0.5 - 0.5 * (2.0 * PI * SYLLABLE_RATE * t).cos();
+                    // Fundamental plus two weaker harmonics
+                    let voice = (1..=3)
+                        .map(|harmonic| {
+                            let harmonic = harmonic as f32;
+                            (2.0 * PI * SPEECH_PITCH * harmonic * t).sin() / harmonic
+                        })
+                        .sum::<f32>()
+                        / 1.83;
+                    amplitude * envelope * voice
+                }
+            }));
+        }
+
+        Self {
+            samples,
+            sample_rate,
+            realtime: false,
+            feeder: None,
+        }
+    }
+
+    /// Pace delivery by the sample clock, like a real device
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, callback: SampleCallback) -> Result<()> {
+        let samples = std::mem::take(&mut self.samples);
+        self.feeder = Some(Feeder::play(samples, self.sample_rate, self.realtime, callback));
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.feeder.as_ref().is_some_and(Feeder::is_finished)
+    }
+}
@@ -1,40 +1,45 @@
+use thehand::config::Config;
+use thehand::history::HistoryRecord;
+use thehand::metrics::Stats;
+use thehand::status::Status;
+use thehand::PipelineEvent;
 use chrono::{DateTime, Local};
+use ratatui::widgets::ListState;
 use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-/// Application state machine
+/// How long the peak-hold marker stays put before it falls
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+/// How fast the held peak falls afterwards (full scale per second)
+const PEAK_FALL_RATE: f32 = 0.5;
+/// Time covered by each bar of the level sparkline
+const LEVEL_HISTORY_STEP: Duration = Duration::from_millis(100);
+/// Sparkline bars kept; more than any terminal is wide
+const LEVEL_HISTORY_LEN: usize = 300;
+/// Input peaks below this count as silence for the quiet-input warning
+const QUIET_PEAK: f32 = 0.003;
+/// How long the input must stay that quiet before warning
+const QUIET_WARNING_AFTER: Duration = Duration::from_secs(10);
+
+/// Input level problems shown in the status line
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum AppState {
-    /// Monitoring for voice
-    Idle,
-    /// Capturing audio
-    Recording,
-    /// Processing with whisper.cpp
-    Transcribing,
-    /// Sending output to focused window
-    Typing,
-    /// Voice detection disabled
-    Muted,
+pub enum LevelWarning {
+    /// The input has been at (near) digital silence for a while
+    Quiet,
+    /// The last recording had this many clipped samples
+    Clipping(usize),
 }
 
-impl AppState {
-    pub fn display_text(&self) -> &'static str {
-        match self {
-            AppState::Idle => "Listening...",
-            AppState::Recording => "Recording... ●",
-            AppState::Transcribing => "Transcribing...",
-            AppState::Typing => "Sent ✓",
-            AppState::Muted => "MUTED",
-        }
-    }
-
-    pub fn color(&self) -> ratatui::style::Color {
-        use ratatui::style::Color;
+impl LevelWarning {
+    pub fn display_text(&self) -> String {
         match self {
-            AppState::Idle => Color::Green,
-            AppState::Recording => Color::Red,
-            AppState::Transcribing => Color::Yellow,
-            AppState::Typing => Color::Green,
-            AppState::Muted => Color::DarkGray,
+            LevelWarning::Quiet => {
+                "Input is nearly silent; check the microphone and its gain".to_string()
+            }
+            LevelWarning::Clipping(samples) => {
+                format!("{} samples clipped; lower the input gain", samples)
+            }
         }
     }
 }
@@ -44,96 +49,300 @@ impl AppState {
 pub struct HistoryEntry {
     pub timestamp: DateTime<Local>,
     pub text: String,
+    /// Whisper's average token probability (0.0-1.0), if known
+    pub confidence: Option<f32>,
+    /// Why the hallucination filter dropped this entry, if it did
+    pub rejected: Option<String>,
+    /// Kept recording of this entry, if any
+    pub audio: Option<PathBuf>,
 }
 
 impl HistoryEntry {
-    pub fn new(text: String) -> Self {
-        Self {
-            timestamp: Local::now(),
-            text,
-        }
-    }
-
     pub fn format_time(&self) -> String {
         self.timestamp.format("%H:%M").to_string()
     }
 }
 
+impl From<&HistoryRecord> for HistoryEntry {
+    fn from(record: &HistoryRecord) -> Self {
+        Self {
+            timestamp: record.timestamp,
+            text: record.text.clone(),
+            confidence: record.confidence,
+            rejected: record.rejected.clone(),
+            audio: record.audio.clone(),
+        }
+    }
+}
+
 /// Application state container
 pub struct AppStateContainer {
-    pub state: AppState,
+    /// State, current text and error, as the pipeline's events left them
+    pub status: Status,
     pub history: VecDeque<HistoryEntry>,
-    pub current_text: String,
     pub audio_level: f32,
-    pub error_message: Option<String>,
+    /// Highest recent input sample and when it was reached
+    held_peak: f32,
+    held_peak_at: Instant,
+    /// Since when the input has been nearly silent
+    quiet_since: Option<Instant>,
+    pub level_warning: Option<LevelWarning>,
+    /// Loudest level of each recent `LEVEL_HISTORY_STEP`, newest last
+    pub level_history: VecDeque<f32>,
+    level_step_start: Instant,
+    /// Configured VAD thresholds, marked on the meter
+    pub voice_threshold: f32,
+    pub silence_threshold: f32,
     pub should_quit: bool,
     pub history_limit: usize,
+    /// Selected row of the history pane, counting only entries that match the filter
+    pub history_state: ListState,
+    /// Text the history pane is filtered by
+    pub history_filter: String,
+    /// Whether keystrokes are going to the history filter
+    pub editing_filter: bool,
+    /// Rows in the history pane, for paging
+    pub history_page: usize,
+    /// Stage timings of recent transcriptions
+    pub stats: Stats,
+    /// Whether the stats panel is shown
+    pub show_stats: bool,
+    /// Whether the diagnostic log pane is shown
+    pub show_log: bool,
 }
 
 impl AppStateContainer {
     pub fn new(history_limit: usize) -> Self {
         Self {
-            state: AppState::Idle,
+            status: Status::new(),
             history: VecDeque::new(),
-            current_text: String::new(),
             audio_level: 0.0,
-            error_message: None,
+            held_peak: 0.0,
+            held_peak_at: Instant::now(),
+            quiet_since: None,
+            level_warning: None,
+            level_history: VecDeque::from(vec![0.0]),
+            level_step_start: Instant::now(),
+            voice_threshold: 0.0,
+            silence_threshold: 0.0,
             should_quit: false,
             history_limit,
+            history_state: ListState::default(),
+            history_filter: String::new(),
+            editing_filter: false,
+            history_page: 10,
+            stats: Stats::default(),
+            show_stats: false,
+            show_log: false,
         }
     }
 
-    /// Add a transcription to history
-    pub fn add_to_history(&mut self, text: String) {
-        let entry = HistoryEntry::new(text);
+    /// Add a saved transcription to history
+    pub fn add_to_history(&mut self, record: &HistoryRecord) {
+        self.push_history(HistoryEntry::from(record));
+    }
+
+    /// Fill the history from saved records, oldest first
+    pub fn load_history(&mut self, records: &[HistoryRecord]) {
+        for record in records {
+            self.push_history(HistoryEntry::from(record));
+        }
+    }
+
+    fn push_history(&mut self, entry: HistoryEntry) {
+        // Keep the same entry selected as the list grows at the top
+        let shifts_selection = self.matches_filter(&entry);
         self.history.push_front(entry);
+        if shifts_selection {
+            if let Some(selected) = self.history_state.selected() {
+                self.history_state.select(Some(selected + 1));
+            }
+        }
 
         // Limit history size
         while self.history.len() > self.history_limit {
             self.history.pop_back();
         }
+        self.clamp_history_selection();
     }
 
-    /// Set the current state
-    pub fn set_state(&mut self, state: AppState) {
-        self.state = state;
+    fn matches_filter(&self, entry: &HistoryEntry) -> bool {
+        self.history_filter.is_empty()
+            || entry
+                .text
+                .to_lowercase()
+                .contains(&self.history_filter.to_lowercase())
+    }
 
-        // Clear error message on state change
-        if state != AppState::Idle {
-            self.error_message = None;
-        }
+    /// History entries matching the filter, newest first
+    pub fn visible_history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history
+            .iter()
+            .filter(|entry| self.matches_filter(entry))
     }
 
-    /// Toggle mute state
-    pub fn toggle_mute(&mut self) {
-        self.state = match self.state {
-            AppState::Muted => AppState::Idle,
-            _ => AppState::Muted,
+    /// Move the history selection by `delta` rows, selecting the newest entry first
+    pub fn move_history_selection(&mut self, delta: isize) {
+        let len = self.visible_history().count();
+        if len == 0 {
+            self.history_state.select(None);
+            return;
+        }
+
+        let selected = match self.history_state.selected() {
+            Some(selected) => selected.saturating_add_signed(delta).min(len - 1),
+            None => 0,
         };
+        self.history_state.select(Some(selected));
+    }
+
+    /// Index into `history` of the selected entry
+    fn selected_history_index(&self) -> Option<usize> {
+        let selected = self.history_state.selected()?;
+        self.history
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.matches_filter(entry))
+            .nth(selected)
+            .map(|(index, _)| index)
+    }
+
+    pub fn selected_history(&self) -> Option<&HistoryEntry> {
+        self.history.get(self.selected_history_index()?)
+    }
+
+    /// Remove the selected entry from history
+    pub fn remove_selected_history(&mut self) -> Option<HistoryEntry> {
+        let entry = self.history.remove(self.selected_history_index()?);
+        self.clamp_history_selection();
+        entry
+    }
+
+    fn clamp_history_selection(&mut self) {
+        let len = self.visible_history().count();
+        if let Some(selected) = self.history_state.selected() {
+            self.history_state
+                .select(len.checked_sub(1).map(|last| selected.min(last)));
+        }
+    }
+
+    /// Start typing a history filter
+    pub fn start_filter(&mut self) {
+        self.editing_filter = true;
+    }
+
+    /// Stop typing the filter, keeping it applied
+    pub fn finish_filter(&mut self) {
+        self.editing_filter = false;
     }
 
-    /// Set error message
-    pub fn set_error(&mut self, message: String) {
-        self.error_message = Some(message);
+    /// Stop typing the filter and show the whole history again
+    pub fn clear_filter(&mut self) {
+        self.editing_filter = false;
+        self.set_filter(String::new());
     }
 
-    /// Clear error message
-    pub fn clear_error(&mut self) {
-        self.error_message = None;
+    pub fn push_filter_char(&mut self, c: char) {
+        let mut filter = std::mem::take(&mut self.history_filter);
+        filter.push(c);
+        self.set_filter(filter);
     }
 
-    /// Update audio level (0.0 - 1.0)
-    pub fn update_audio_level(&mut self, level: f32) {
+    pub fn pop_filter_char(&mut self) {
+        let mut filter = std::mem::take(&mut self.history_filter);
+        filter.pop();
+        self.set_filter(filter);
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.history_filter = filter;
+        let any = self.visible_history().next().is_some();
+        self.history_state.select(any.then_some(0));
+    }
+
+    /// Show or hide the stats panel
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    /// Show or hide the diagnostic log pane
+    pub fn toggle_log(&mut self) {
+        self.show_log = !self.show_log;
+    }
+
+    /// Update audio level and sample peak (0.0 - 1.0)
+    pub fn update_audio_level(&mut self, level: f32, peak: f32) {
         self.audio_level = level.clamp(0.0, 1.0);
+        let peak = peak.clamp(0.0, 1.0);
+
+        // Keep the loudest level of each step for the sparkline
+        if self.level_step_start.elapsed() >= LEVEL_HISTORY_STEP {
+            self.level_step_start = Instant::now();
+            self.level_history.push_back(0.0);
+            if self.level_history.len() > LEVEL_HISTORY_LEN {
+                self.level_history.pop_front();
+            }
+        }
+        if let Some(last) = self.level_history.back_mut() {
+            *last = last.max(self.audio_level);
+        }
+
+        if peak >= self.peak_hold() {
+            self.held_peak = peak;
+            self.held_peak_at = Instant::now();
+        }
+
+        // Warn when the input stays at digital silence
+        if peak < QUIET_PEAK {
+            let since = *self.quiet_since.get_or_insert_with(Instant::now);
+            if since.elapsed() >= QUIET_WARNING_AFTER && self.level_warning.is_none() {
+                self.level_warning = Some(LevelWarning::Quiet);
+            }
+        } else {
+            self.quiet_since = None;
+            if self.level_warning == Some(LevelWarning::Quiet) {
+                self.level_warning = None;
+            }
+        }
+    }
+
+    /// Set the VAD thresholds marked on the meter
+    pub fn set_thresholds(&mut self, voice_threshold: f32, silence_threshold: f32) {
+        self.voice_threshold = voice_threshold;
+        self.silence_threshold = silence_threshold;
     }
 
-    /// Set current text being processed
-    pub fn set_current_text(&mut self, text: String) {
-        self.current_text = text;
+    /// Peak-hold marker position: the recent peak, held for a moment and then falling
+    pub fn peak_hold(&self) -> f32 {
+        let falling_for = self.held_peak_at.elapsed().saturating_sub(PEAK_HOLD);
+        (self.held_peak - PEAK_FALL_RATE * falling_for.as_secs_f32()).max(0.0)
     }
 
-    /// Clear current text
-    pub fn clear_current_text(&mut self) {
-        self.current_text.clear();
+    /// Warn about clipping in the last recording, or clear an earlier warning
+    pub fn report_clipping(&mut self, clipped_samples: usize) {
+        if clipped_samples > 0 {
+            self.level_warning = Some(LevelWarning::Clipping(clipped_samples));
+        } else if matches!(self.level_warning, Some(LevelWarning::Clipping(_))) {
+            self.level_warning = None;
+        }
+    }
+
+    /// Reflect a pipeline event in the UI state
+    pub fn handle_event(&mut self, event: PipelineEvent, config: &Config) {
+        self.status.handle_event(&event, config);
+        match event {
+            PipelineEvent::Level { rms, peak } => self.update_audio_level(rms, peak),
+            PipelineEvent::RecordingStopped { clipped_samples, .. } => {
+                self.report_clipping(clipped_samples)
+            }
+            PipelineEvent::Transcribed(record) => {
+                self.stats.push(record.timings);
+                // Entries that failed to type stay out of the pane
+                if record.typed || record.rejected.is_some() {
+                    self.add_to_history(&record);
+                }
+            }
+            _ => {}
+        }
     }
 }
@@ -0,0 +1,236 @@
+use crate::config::Config;
+use crate::pipeline::PipelineEvent;
+
+/// Application state machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppState {
+    /// Loading the transcription model
+    LoadingModel,
+    /// Monitoring for voice
+    Idle,
+    /// Capturing audio
+    Recording,
+    /// Processing with whisper.cpp
+    Transcribing,
+    /// Sending output to focused window
+    Typing,
+    /// Voice detection disabled
+    Muted,
+    /// Input device failed; waiting to reopen it
+    DeviceLost,
+    /// The transcription model couldn't be loaded; speech is ignored
+    ModelFailed,
+}
+
+impl AppState {
+    /// Every state, so metrics can report the ones never entered too
+    pub const ALL: [AppState; 8] = [
+        AppState::LoadingModel,
+        AppState::Idle,
+        AppState::Recording,
+        AppState::Transcribing,
+        AppState::Typing,
+        AppState::Muted,
+        AppState::DeviceLost,
+        AppState::ModelFailed,
+    ];
+
+    /// Machine-readable name, used as a metrics label
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppState::LoadingModel => "loading_model",
+            AppState::Idle => "idle",
+            AppState::Recording => "recording",
+            AppState::Transcribing => "transcribing",
+            AppState::Typing => "typing",
+            AppState::Muted => "muted",
+            AppState::DeviceLost => "device_lost",
+            AppState::ModelFailed => "model_failed",
+        }
+    }
+
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            AppState::LoadingModel => "Loading model...",
+            AppState::Idle => "Listening...",
+            AppState::Recording => "Recording... ●",
+            AppState::Transcribing => "Transcribing...",
+            AppState::Typing => "Sent ✓",
+            AppState::Muted => "MUTED",
+            AppState::DeviceLost => "Device lost, reconnecting...",
+            AppState::ModelFailed => "Model failed to load",
+        }
+    }
+}
+
+/// What a front end shows about the pipeline: its state, the text being
+/// worked on and the latest error
+///
+/// [`Status::handle_event`] is the one place pipeline events turn into
+/// state, for the TUI and for tests alike.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub state: AppState,
+    pub current_text: String,
+    /// Uncommitted live hypothesis shown after `current_text`
+    pub partial_text: String,
+    pub error_message: Option<String>,
+    /// Whether the input device is gone and being reopened
+    pub device_lost: bool,
+    /// Whether the model failed to load, leaving nothing to transcribe with
+    pub model_failed: bool,
+    /// Whether voice activation is muted, as the pipeline last reported
+    pub muted: bool,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Self {
+            state: AppState::Idle,
+            current_text: String::new(),
+            partial_text: String::new(),
+            error_message: None,
+            device_lost: false,
+            model_failed: false,
+            muted: false,
+        }
+    }
+
+    /// Set the current state
+    pub fn set_state(&mut self, state: AppState) {
+        self.state = state;
+
+        // Clear error message on state change
+        if state != AppState::Idle {
+            self.error_message = None;
+        }
+    }
+
+    /// Where the app settles between utterances
+    fn resting_state(&self) -> AppState {
+        if self.muted {
+            AppState::Muted
+        } else if self.model_failed {
+            AppState::ModelFailed
+        } else if self.device_lost {
+            AppState::DeviceLost
+        } else {
+            AppState::Idle
+        }
+    }
+
+    /// Set error message
+    pub fn set_error(&mut self, message: String) {
+        self.error_message = Some(message);
+    }
+
+    /// Clear error message
+    pub fn clear_error(&mut self) {
+        self.error_message = None;
+    }
+
+    /// Set current text being processed
+    pub fn set_current_text(&mut self, text: String) {
+        self.current_text = text;
+    }
+
+    /// Set the live hypothesis while streaming
+    pub fn set_partial_text(&mut self, text: String) {
+        self.partial_text = text;
+    }
+
+    /// Clear current text
+    pub fn clear_current_text(&mut self) {
+        self.current_text.clear();
+        self.partial_text.clear();
+    }
+
+    /// Reflect a pipeline event in the state, text and error shown
+    pub fn handle_event(&mut self, event: &PipelineEvent, config: &Config) {
+        match event {
+            PipelineEvent::ModelLoading { elapsed } => {
+                // Show model load progress until the transcriber is ready
+                if self.state == AppState::LoadingModel {
+                    self.set_current_text(format!(
+                        "Loading {} ({:.0}s)",
+                        config.whisper.model_path,
+                        elapsed.as_secs_f32()
+                    ));
+                }
+            }
+            PipelineEvent::ModelLoaded => {
+                if self.state == AppState::LoadingModel {
+                    self.set_state(self.resting_state());
+                    self.clear_current_text();
+                }
+            }
+            PipelineEvent::ModelFailed(msg) => {
+                self.model_failed = true;
+                self.clear_current_text();
+                // After the state change, which would clear the error
+                self.set_state(self.resting_state());
+                self.set_error(msg.clone());
+            }
+            PipelineEvent::Level { .. } | PipelineEvent::SilenceDetected => {}
+            PipelineEvent::VoiceDetected => self.clear_error(),
+            PipelineEvent::RecordingStarted => {
+                self.set_state(AppState::Recording);
+                self.clear_current_text();
+            }
+            PipelineEvent::RecordingStopped { .. } => self.set_state(AppState::Transcribing),
+            PipelineEvent::RecordingCancelled | PipelineEvent::Ignored => {
+                self.set_state(self.resting_state());
+                self.clear_current_text();
+            }
+            PipelineEvent::Partial { committed, partial } => {
+                self.set_current_text(committed.clone());
+                self.set_partial_text(partial.clone());
+            }
+            PipelineEvent::Typing(text) => {
+                self.set_current_text(text.clone());
+                self.set_state(AppState::Typing);
+            }
+            PipelineEvent::Transcribed(record) => {
+                if record.rejected.is_none() {
+                    self.clear_current_text();
+                }
+                self.set_state(self.resting_state());
+            }
+            PipelineEvent::Failed(msg) => {
+                self.set_error(msg.clone());
+                self.set_state(self.resting_state());
+            }
+            PipelineEvent::Error(msg) => self.set_error(msg.clone()),
+            PipelineEvent::DeviceLost => {
+                self.device_lost = true;
+                // Set directly so the error that explains it stays up; a
+                // running transcription or load finishes first
+                if matches!(self.state, AppState::Idle | AppState::Recording) {
+                    self.state = AppState::DeviceLost;
+                }
+            }
+            PipelineEvent::DeviceRestored(name) => {
+                self.device_lost = false;
+                if self.state == AppState::DeviceLost {
+                    self.set_state(AppState::Idle);
+                    self.set_current_text(format!("Listening on {}", name));
+                }
+            }
+            PipelineEvent::Muted(muted) => {
+                self.muted = *muted;
+                // Shown at once; a transcription still running settles back here
+                if *muted {
+                    self.state = AppState::Muted;
+                } else if self.state == AppState::Muted {
+                    self.state = self.resting_state();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::audio::{self, AudioCapture, Recording};
+use crate::config::StreamingConfig;
+use crate::filter::normalize;
+use crate::transcribe::{Transcriber, Transcription, TranscriptionJob};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Shortest stretch of audio worth a partial transcription (seconds)
+const MIN_PASS_AUDIO: f32 = 1.0;
+
+/// Runs streaming passes over the recording in progress
+pub struct Streamer {
+    config: StreamingConfig,
+    sample_rate: u32,
+    /// Where each pass's audio is saved, if the backend reads from disk
+    wav_dir: Option<PathBuf>,
+    session: Option<StreamingSession>,
+    job: Option<(Recording, TranscriptionJob)>,
+    last_pass: Instant,
+}
+
+impl Streamer {
+    pub fn new(config: &StreamingConfig, sample_rate: u32, wav_dir: Option<PathBuf>) -> Self {
+        Self {
+            config: config.clone(),
+            sample_rate,
+            wav_dir,
+            session: None,
+            job: None,
+            last_pass: Instant::now(),
+        }
+    }
+
+    /// Begin streaming a new utterance, if streaming is enabled
+    pub fn start(&mut self) {
+        if self.config.enabled {
+            self.session = Some(StreamingSession::new());
+            self.discard_pass();
+            self.last_pass = Instant::now();
+        }
+    }
+
+    /// Stop streaming, returning the words already typed
+    pub fn stop(&mut self) -> String {
+        self.discard_pass();
+        self.session
+            .take()
+            .map(|session| session.typed_text())
+            .unwrap_or_default()
+    }
+
+    /// Kill the running pass, if any, and remove its audio file
+    fn discard_pass(&mut self) {
+        // Dropping the job kills whisper before its input goes away
+        if let Some((recording, job)) = self.job.take() {
+            drop(job);
+            if let Some(ref path) = recording.path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Poll the running pass or start the next one
+    ///
+    /// Returns the session whenever a pass has updated it.
+    pub fn tick(
+        &mut self,
+        audio: &AudioCapture,
+        transcriber: &Transcriber,
+    ) -> Option<&mut StreamingSession> {
+        self.session.as_ref()?;
+
+        // Fold in a finished pass
+        if let Some((recording, job)) = self.job.as_mut() {
+            let result = job.poll()?;
+            let window_duration = recording.duration();
+            self.discard_pass();
+
+            // Partial passes are best effort; the final transcription reports errors
+            let session = self.session.as_mut()?;
+            match result {
+                Ok(transcription) => {
+                    session.update(
+                        &transcription,
+                        window_duration,
+                        self.config.window,
+                        self.sample_rate,
+                    );
+                    return Some(session);
+                }
+                Err(e) => debug!("Partial transcription failed: {}", e),
+            }
+            return None;
+        }
+
+        // Start the next pass over the current window
+        if self.last_pass.elapsed() < Duration::from_secs_f32(self.config.interval) {
+            return None;
+        }
+        self.last_pass = Instant::now();
+
+        let window_start = self.session.as_ref()?.window_start();
+        let samples = audio.snapshot(window_start)?;
+        if (samples.len() as f32) < MIN_PASS_AUDIO * self.sample_rate as f32 {
+            return None;
+        }
+
+        let path = match self.wav_dir {
+            Some(ref dir) => Some(audio::save_wav(dir, &samples, self.sample_rate).ok()?),
+            None => None,
+        };
+        let recording = Recording {
+            path,
+            samples,
+            sample_rate: self.sample_rate,
+            voiced_ratio: 1.0,
+            peak: 0.0,
+            clipped_samples: 0,
+            silence_wait: 0.0,
+            wav_write: 0.0,
+        };
+
+        match transcriber.start(&recording) {
+            Ok(job) => self.job = Some((recording, job)),
+            Err(e) => {
+                debug!("Failed to start a partial transcription: {:#}", e);
+                if let Some(ref path) = recording.path {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Live transcription of an utterance while it is still being recorded
+///
+/// Each pass transcribes a window of audio starting at `window_start`. Words
+/// two consecutive passes agree on are committed and never change again.
+/// Once the window grows past its limit, it slides forward past the segments
+/// that are fully committed, so each pass overlaps the previous one.
+#[derive(Debug, Default)]
+pub struct StreamingSession {
+    /// Offset of the current window into the recording (samples)
+    window_start: usize,
+    /// All committed words, from earlier windows and the current one
+    committed: Vec<String>,
+    /// How many committed words lie inside the current window
+    committed_in_window: usize,
+    /// Words of the previous hypothesis for the current window
+    previous: Vec<String>,
+    /// Uncommitted tail of the latest hypothesis
+    partial: Vec<String>,
+    /// How many committed words have been handed out for typing
+    typed: usize,
+}
+
+impl StreamingSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the next pass should start reading the recording (samples)
+    pub fn window_start(&self) -> usize {
+        self.window_start
+    }
+
+    /// Fold in a hypothesis for the audio from `window_start` onwards
+    pub fn update(
+        &mut self,
+        transcription: &Transcription,
+        window_duration: f32,
+        max_window: f32,
+        sample_rate: u32,
+    ) {
+        let words: Vec<String> = transcription
+            .text
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        // Commit whatever this pass and the previous one agree on
+        let stable = common_prefix(&self.previous, &words);
+        if stable > self.committed_in_window {
+            self.committed
+                .extend_from_slice(&words[self.committed_in_window..stable]);
+            self.committed_in_window = stable;
+        }
+
+        self.partial = words
+            .get(self.committed_in_window..)
+            .map(<[String]>::to_vec)
+            .unwrap_or_default();
+        self.previous = words;
+
+        if window_duration > max_window {
+            self.slide_window(transcription, sample_rate);
+        }
+    }
+
+    /// Move the window past the leading segments that are fully committed
+    fn slide_window(&mut self, transcription: &Transcription, sample_rate: u32) {
+        let mut words_before = 0;
+        let mut slide = None;
+
+        for segment in &transcription.segments {
+            let words = segment.text.split_whitespace().count();
+            if words_before + words > self.committed_in_window {
+                break;
+            }
+            words_before += words;
+            slide = Some((words_before, segment.end));
+        }
+
+        if let Some((words, end)) = slide {
+            self.window_start += (end * sample_rate as f32) as usize;
+            self.committed_in_window -= words;
+            self.previous.drain(..words.min(self.previous.len()));
+        }
+    }
+
+    /// Committed text so far
+    pub fn committed_text(&self) -> String {
+        self.committed.join(" ")
+    }
+
+    /// Latest uncommitted hypothesis
+    pub fn partial_text(&self) -> String {
+        self.partial.join(" ")
+    }
+
+    /// Committed words not yet typed, if any
+    pub fn take_untyped(&mut self) -> Option<String> {
+        if self.typed >= self.committed.len() {
+            return None;
+        }
+        let text = self.committed[self.typed..].join(" ");
+        self.typed = self.committed.len();
+        Some(text)
+    }
+
+    /// Words already handed out for typing
+    pub fn typed_text(&self) -> String {
+        self.committed[..self.typed].join(" ")
+    }
+}
+
+/// Number of leading words two hypotheses agree on, ignoring case and punctuation
+fn common_prefix(a: &[String], b: &[String]) -> usize {
+    a.iter()
+        .zip(b)
+        .take_while(|(x, y)| normalize(x) == normalize(y))
+        .count()
+}
+
+/// The words of `text` after `typed`, if `text` starts with those words
+///
+/// Words compare like the passes do, so a final transcription that only
+/// punctuates the typed words differently still continues them.
+pub fn continuation(text: &str, typed: &str) -> Option<String> {
+    let words: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+    let typed: Vec<String> = typed.split_whitespace().map(str::to_string).collect();
+    if common_prefix(&typed, &words) < typed.len() {
+        return None;
+    }
+    Some(words[typed.len()..].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::RawSegment;
+
+    fn pass(session: &mut StreamingSession, text: &str) {
+        let segment = RawSegment {
+            end: 2.0,
+            text: text.to_string(),
+            token_probs: Vec::new(),
+        };
+        session.update(&Transcription::from_segments(vec![segment], None), 2.0, 15.0, 16000);
+    }
+
+    #[test]
+    fn words_two_passes_agree_on_are_typed_once() {
+        let mut session = StreamingSession::new();
+        pass(&mut session, "Hello there");
+        assert_eq!(session.take_untyped(), None);
+
+        pass(&mut session, "Hello world, how");
+        assert_eq!(session.take_untyped().as_deref(), Some("Hello"));
+        pass(&mut session, "Hello world, how are you");
+        assert_eq!(session.take_untyped().as_deref(), Some("world, how"));
+        assert_eq!(session.take_untyped(), None);
+
+        assert_eq!(session.typed_text(), "Hello world, how");
+        assert_eq!(session.partial_text(), "are you");
+    }
+
+    #[test]
+    fn continuation_follows_the_typed_words() {
+        assert_eq!(
+            continuation("Hello world, how are you?", "Hello world, how").as_deref(),
+            Some("are you?")
+        );
+        // Only punctuation and case differ from what was typed
+        assert_eq!(
+            continuation("hello world how are you?", "Hello world, how").as_deref(),
+            Some("are you?")
+        );
+        assert_eq!(continuation("Hello world.", "Hello world.").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn continuation_refuses_a_final_that_disagrees() {
+        // Whisper heard it differently, or merged two typed words into one
+        assert_eq!(continuation("Hello word, how are you?", "Hello world, how"), None);
+        assert_eq!(continuation("Cannot go", "Can not"), None);
+        assert_eq!(continuation("Hello", "Hello world"), None);
+    }
+}
@@ -1,46 +1,458 @@
+use crate::audio::Recording;
+use crate::config::{TranscriptionBackend, WhisperConfig};
+#[cfg(feature = "whisper-rs")]
+use crate::whisper_local::{LocalJob, LocalModel};
 use anyhow::{Context, Result};
-use std::path::Path;
-use std::process::Command;
-
-/// Transcribe audio file using whisper.cpp
-pub fn transcribe(
-    whisper_binary: &str,
-    model_path: &str,
-    audio_file: &Path,
-) -> Result<String> {
-    let output = Command::new(whisper_binary)
-        .arg("-m")
-        .arg(model_path)
-        .arg("-f")
-        .arg(audio_file)
-        .arg("--no-timestamps")
-        .arg("--output-txt")
-        .arg("--output-file")
-        .arg("-") // Output to stdout
-        .output()
-        .context(format!("Failed to execute whisper binary at {}", whisper_binary))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Whisper.cpp failed: {}", stderr);
-    }
-
-    let transcription = String::from_utf8(output.stdout)
-        .context("Failed to parse whisper output as UTF-8")?;
-
-    // Clean up the transcription
-    let cleaned = transcription
-        .trim()
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .collect::<Vec<_>>()
-        .join(" ");
-
-    if cleaned.is_empty() {
-        anyhow::bail!("Whisper returned empty transcription");
-    }
-
-    Ok(cleaned)
+use serde::Deserialize;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, trace, warn};
+
+/// A piece of a transcription, ending at a known point in the audio
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// End offset into the audio (seconds)
+    pub end: f32,
+    pub text: String,
+}
+
+/// Result of transcribing one recording
+#[derive(Debug, Clone)]
+pub struct Transcription {
+    /// Segment texts joined into a single line
+    pub text: String,
+    pub segments: Vec<Segment>,
+    /// Language whisper used or detected
+    pub language: Option<String>,
+    /// Mean log probability over all tokens, if whisper reported them
+    pub avg_logprob: Option<f32>,
+}
+
+impl Transcription {
+    /// Average token probability (0.0-1.0), if known
+    pub fn confidence(&self) -> Option<f32> {
+        self.avg_logprob.map(f32::exp)
+    }
+}
+
+/// A segment as reported by a backend, before confidences are averaged
+pub(crate) struct RawSegment {
+    pub end: f32,
+    pub text: String,
+    /// Probabilities of the segment's non-special tokens
+    pub token_probs: Vec<f32>,
+}
+
+impl Transcription {
+    /// Assemble a transcription from backend segments
+    pub(crate) fn from_segments(
+        raw_segments: Vec<RawSegment>,
+        language: Option<String>,
+    ) -> Self {
+        let mut all_logprobs = Vec::new();
+        let segments: Vec<Segment> = raw_segments
+            .into_iter()
+            .map(|segment| {
+                all_logprobs.extend(
+                    segment
+                        .token_probs
+                        .iter()
+                        .map(|p| p.max(f32::MIN_POSITIVE).ln()),
+                );
+
+                Segment {
+                    end: segment.end,
+                    text: segment.text.trim().to_string(),
+                }
+            })
+            .collect();
+
+        // Clean up the transcription
+        let text = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            text,
+            segments,
+            language,
+            avg_logprob: mean(&all_logprobs),
+        }
+    }
+}
+
+/// Top level of whisper.cpp's `--output-json-full` file
+#[derive(Debug, Deserialize)]
+struct WhisperJson {
+    #[serde(default)]
+    result: Option<WhisperResult>,
+    #[serde(default)]
+    transcription: Vec<WhisperSegment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperResult {
+    language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperSegment {
+    offsets: WhisperOffsets,
+    text: String,
+    #[serde(default)]
+    tokens: Vec<WhisperToken>,
+}
+
+/// Offsets in milliseconds
+#[derive(Debug, Deserialize)]
+struct WhisperOffsets {
+    to: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperToken {
+    text: String,
+    p: f32,
+}
+
+impl WhisperToken {
+    /// Special tokens like "[_BEG_]" or "[_TT_150]" carry no speech
+    fn is_special(&self) -> bool {
+        self.text.starts_with("[_")
+    }
+}
+
+/// Why a transcription did not produce text
+#[derive(Debug, Error)]
+pub enum TranscribeError {
+    /// Whisper ran longer than the configured timeout
+    #[error("Transcription timed out after {0:.0}s")]
+    Timeout(f32),
+    /// Cancelled by the user
+    #[error("Transcription cancelled")]
+    Cancelled,
+    /// Whisper heard nothing it could write down
+    #[error("Transcription failed: Whisper returned empty transcription")]
+    Empty,
+    /// Whisper failed or produced unusable output
+    #[error("Transcription failed: {0:#}")]
+    Failed(#[from] anyhow::Error),
+}
+
+/// The configured transcription backend, ready to start jobs
+pub enum Transcriber {
+    /// Spawn the whisper.cpp binary for each recording
+    Cli(WhisperConfig),
+    /// Run a model loaded once at startup via whisper-rs
+    #[cfg(feature = "whisper-rs")]
+    InProcess(LocalModel),
+}
+
+impl Transcriber {
+    /// Prepare the configured backend
+    ///
+    /// For the in-process backend this loads the model, which can take a while.
+    pub fn load(config: &WhisperConfig) -> Result<Self> {
+        match config.backend {
+            TranscriptionBackend::Cli => Ok(Transcriber::Cli(config.clone())),
+            #[cfg(feature = "whisper-rs")]
+            TranscriptionBackend::WhisperRs => Ok(Transcriber::InProcess(LocalModel::load(config)?)),
+            #[cfg(not(feature = "whisper-rs"))]
+            TranscriptionBackend::WhisperRs => {
+                anyhow::bail!("TheHand was built without the whisper-rs feature")
+            }
+        }
+    }
+
+    /// A transcriber sharing this one's model but decoding with `config`'s
+    /// settings; `config.model_path` is not loaded
+    pub fn with_config(&self, config: &WhisperConfig) -> Self {
+        match self {
+            Transcriber::Cli(_) => Transcriber::Cli(config.clone()),
+            #[cfg(feature = "whisper-rs")]
+            Transcriber::InProcess(model) => Transcriber::InProcess(model.with_config(config)),
+        }
+    }
+
+    /// Start transcribing a recording in the background
+    pub fn start(&self, recording: &Recording) -> Result<TranscriptionJob> {
+        match self {
+            Transcriber::Cli(config) => {
+                let path = recording
+                    .path
+                    .as_deref()
+                    .context("The whisper.cpp backend needs a saved WAV file")?;
+                Ok(TranscriptionJob::Process(ProcessJob::spawn(
+                    config,
+                    path,
+                    recording.duration(),
+                )?))
+            }
+            #[cfg(feature = "whisper-rs")]
+            Transcriber::InProcess(model) => Ok(TranscriptionJob::InProcess(model.start(recording))),
+        }
+    }
+
+    /// Transcribe a recording and wait for the result
+    pub fn transcribe(&self, recording: &Recording) -> Result<Transcription, TranscribeError> {
+        let mut job = self.start(recording)?;
+        loop {
+            if let Some(result) = job.poll() {
+                return result;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+/// A transcription running in the background
+pub enum TranscriptionJob {
+    Process(ProcessJob),
+    #[cfg(feature = "whisper-rs")]
+    InProcess(LocalJob),
+}
+
+impl TranscriptionJob {
+    /// Check whether the transcription has finished (non-blocking)
+    ///
+    /// Returns `None` while it is still running, and the result exactly once.
+    pub fn poll(&mut self) -> Option<Result<Transcription, TranscribeError>> {
+        match self {
+            TranscriptionJob::Process(job) => job.poll(),
+            #[cfg(feature = "whisper-rs")]
+            TranscriptionJob::InProcess(job) => job.poll(),
+        }
+    }
+
+    /// Stop the transcription and report it as cancelled
+    pub fn cancel(&mut self) -> TranscribeError {
+        match self {
+            TranscriptionJob::Process(job) => job.cancel(),
+            #[cfg(feature = "whisper-rs")]
+            TranscriptionJob::InProcess(job) => job.cancel(),
+        }
+    }
+}
+
+/// A finished transcription, unless whisper heard nothing
+pub(crate) fn non_empty(transcription: Transcription) -> Result<Transcription, TranscribeError> {
+    if transcription.text.is_empty() {
+        Err(TranscribeError::Empty)
+    } else {
+        Ok(transcription)
+    }
+}
+
+/// Transcription timeout for a recording of the given length
+pub(crate) fn timeout_for(config: &WhisperConfig, duration: f32) -> Duration {
+    Duration::from_secs_f32(config.timeout_secs + config.timeout_scale * duration)
+}
+
+/// A whisper.cpp process running in the background
+///
+/// Whisper runs in its own process group so that cancelling, timing out or
+/// dropping the job kills it along with any children it spawned.
+pub struct ProcessJob {
+    child: Child,
+    stderr: Option<JoinHandle<String>>,
+    json_path: PathBuf,
+    started: Instant,
+    timeout: Duration,
+    finished: bool,
+}
+
+impl ProcessJob {
+    /// Start transcribing an audio file with whisper.cpp
+    fn spawn(config: &WhisperConfig, audio_file: &Path, duration: f32) -> Result<Self> {
+        // whisper.cpp appends ".json" to the output file prefix
+        let output_prefix = audio_file.with_extension("");
+        let json_path = audio_file.with_extension("json");
+
+        let mut command = Command::new(&config.binary_path);
+        command
+            .arg("-m")
+            .arg(&config.model_path)
+            .arg("-f")
+            .arg(audio_file)
+            .arg("--no-prints")
+            .arg("--output-json-full")
+            .arg("--output-file")
+            .arg(&output_prefix)
+            .args(decoding_args(config))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .process_group(0);
+
+        debug!(
+            binary = %config.binary_path,
+            model = %config.model_path,
+            file = %audio_file.display(),
+            "Starting whisper"
+        );
+        let mut child = command
+            .spawn()
+            .context(format!("Failed to execute whisper binary at {}", config.binary_path))?;
+
+        // Drain stderr so a chatty whisper can't block on a full pipe
+        let stderr = child.stderr.take().map(|mut pipe| {
+            thread::spawn(move || {
+                let mut output = String::new();
+                let _ = pipe.read_to_string(&mut output);
+                output
+            })
+        });
+
+        Ok(Self {
+            child,
+            stderr,
+            json_path,
+            started: Instant::now(),
+            timeout: timeout_for(config, duration),
+            finished: false,
+        })
+    }
+
+    fn poll(&mut self) -> Option<Result<Transcription, TranscribeError>> {
+        if self.finished {
+            return None;
+        }
+
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                if self.started.elapsed() < self.timeout {
+                    return None;
+                }
+                warn!(pid = self.child.id(), "Whisper timed out, killing it");
+                self.kill();
+                return Some(Err(TranscribeError::Timeout(self.timeout.as_secs_f32())));
+            }
+            Err(e) => {
+                self.kill();
+                return Some(Err(anyhow::Error::new(e)
+                    .context("Failed to wait for whisper")
+                    .into()));
+            }
+        };
+        self.finished = true;
+
+        let stderr = self
+            .stderr
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        trace!(%status, stderr = stderr.trim(), "Whisper exited");
+        Some(
+            self.finish(status.success(), &stderr)
+                .map_err(TranscribeError::from)
+                .and_then(non_empty),
+        )
+    }
+
+    fn cancel(&mut self) -> TranscribeError {
+        self.kill();
+        TranscribeError::Cancelled
+    }
+
+    fn finish(&self, success: bool, stderr: &str) -> Result<Transcription> {
+        if !success {
+            anyhow::bail!("Whisper.cpp failed: {}", stderr.trim());
+        }
+
+        let json = std::fs::read_to_string(&self.json_path)
+            .context(format!("Whisper did not write JSON output to {:?}", self.json_path))?;
+        parse_json(&json)
+    }
+
+    /// Kill whisper's whole process group and reap it
+    fn kill(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        // The child leads its own process group, so -pid addresses all of it
+        unsafe {
+            libc::kill(-(self.child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for ProcessJob {
+    fn drop(&mut self) {
+        self.kill();
+        let _ = std::fs::remove_file(&self.json_path);
+    }
+}
+
+/// Parse whisper.cpp JSON output into a transcription
+pub fn parse_json(json: &str) -> Result<Transcription> {
+    let parsed: WhisperJson =
+        serde_json::from_str(json).context("Failed to parse whisper JSON output")?;
+
+    let segments = parsed
+        .transcription
+        .into_iter()
+        .map(|segment| RawSegment {
+            end: segment.offsets.to as f32 / 1000.0,
+            text: segment.text,
+            token_probs: segment
+                .tokens
+                .iter()
+                .filter(|token| !token.is_special())
+                .map(|token| token.p)
+                .collect(),
+        })
+        .collect();
+
+    Ok(Transcription::from_segments(
+        segments,
+        parsed.result.and_then(|result| result.language),
+    ))
+}
+
+fn mean(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f32>() / values.len() as f32)
+}
+
+/// Language, translation, prompt and decoding flags for whisper.cpp
+fn decoding_args(config: &WhisperConfig) -> Vec<String> {
+    let mut args = vec!["-l".to_string(), config.language.clone()];
+
+    if config.translate {
+        args.push("-tr".to_string());
+    }
+    if let Some(ref prompt) = config.initial_prompt {
+        args.push("--prompt".to_string());
+        args.push(prompt.clone());
+    }
+    if let Some(threads) = config.threads {
+        args.push("-t".to_string());
+        args.push(threads.to_string());
+    }
+    if let Some(beam_size) = config.beam_size {
+        args.push("-bs".to_string());
+        args.push(beam_size.to_string());
+    }
+    if let Some(temperature) = config.temperature {
+        args.push("-tp".to_string());
+        args.push(temperature.to_string());
+    }
+
+    args
 }
 
 /// Clean up temporary audio file
@@ -51,3 +463,11 @@ pub fn cleanup_audio_file(path: &Path) -> Result<()> {
     }
     Ok(())
 }
+
+/// Model file name without its directory, for the history
+pub fn model_name(model_path: &str) -> String {
+    Path::new(model_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| model_path.to_string())
+}
@@ -1,19 +1,56 @@
-use anyhow::Result;
-use enigo::{Enigo, Key, KeyboardControllable};
+use anyhow::{Context, Result};
+use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+use std::process::Command;
 use std::thread;
 use std::time::Duration;
 
+/// Where finished transcriptions go
+pub trait OutputSink {
+    /// Deliver text, e.g. by typing it into the focused window
+    fn type_text(&mut self, text: &str) -> Result<()>;
+
+    /// Title of the window receiving the text, for the history
+    fn focused_window(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Types into whichever window has keyboard focus
+pub struct KeyboardSink {
+    /// Delay between keystrokes (milliseconds)
+    keystroke_delay: u64,
+}
+
+impl KeyboardSink {
+    pub fn new(keystroke_delay: u64) -> Self {
+        Self { keystroke_delay }
+    }
+}
+
+impl OutputSink for KeyboardSink {
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        type_text(text, self.keystroke_delay)
+    }
+
+    fn focused_window(&self) -> Option<String> {
+        focused_window()
+    }
+}
+
 /// Type text into the focused window
 pub fn type_text(text: &str, keystroke_delay_ms: u64) -> Result<()> {
-    let mut enigo = Enigo::new();
+    let mut enigo = Enigo::new(&Settings::default())
+        .context("Failed to connect to the display for typing")?;
     let delay = Duration::from_millis(keystroke_delay_ms);
 
     for c in text.chars() {
         // Type the character
         if c == '\n' {
-            enigo.key_click(Key::Return);
+            enigo.key(Key::Return, Direction::Click)
+                .context("Failed to press Return")?;
         } else {
-            enigo.key_sequence(&c.to_string());
+            enigo.text(&c.to_string())
+                .context("Failed to type text")?;
         }
 
         // Small delay between keystrokes for reliability
@@ -24,3 +61,17 @@ pub fn type_text(text: &str, keystroke_delay_ms: u64) -> Result<()> {
 
     Ok(())
 }
+
+/// Title of the focused window, if `xdotool` is installed and can tell
+pub fn focused_window() -> Option<String> {
+    let output = Command::new("xdotool")
+        .args(["getactivewindow", "getwindowname"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let title = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!title.is_empty()).then_some(title)
+}
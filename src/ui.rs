@@ -1,39 +1,64 @@
-use crate::state::AppStateContainer;
+use crate::logging::LogBuffer;
+use crate::state::{AppStateContainer, HistoryEntry};
+use thehand::status::AppState;
 use ratatui::{
-    backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline},
     Frame,
 };
 
 /// Render the UI
-pub fn render<B: Backend>(frame: &mut Frame, app: &AppStateContainer) {
+pub fn render(frame: &mut Frame, app: &mut AppStateContainer, logs: &LogBuffer) {
     let size = frame.size();
 
     // Main layout
+    let stats_height = if app.show_stats { 4 } else { 0 };
+    let log_height = if app.show_log { LOG_PANE_HEIGHT } else { 0 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([
-            Constraint::Length(3), // Status + VU meter
-            Constraint::Min(10),   // History
-            Constraint::Length(3), // Current text
-            Constraint::Length(3), // Controls
+            Constraint::Length(4),            // Status + VU meter
+            Constraint::Min(10),              // History
+            Constraint::Length(log_height),   // Diagnostic log, if shown
+            Constraint::Length(stats_height), // Stats, if shown
+            Constraint::Length(3),            // Current text
+            Constraint::Length(3),            // Controls
         ])
         .split(size);
 
     render_status(frame, chunks[0], app);
     render_history(frame, chunks[1], app);
-    render_current(frame, chunks[2], app);
-    render_controls(frame, chunks[3]);
+    if app.show_log {
+        render_log(frame, chunks[2], logs);
+    }
+    if app.show_stats {
+        render_stats(frame, chunks[3], app);
+    }
+    render_current(frame, chunks[4], app);
+    render_controls(frame, chunks[5]);
+}
+
+/// Colour of the status line in each state
+fn color(state: AppState) -> Color {
+    match state {
+        AppState::LoadingModel => Color::Yellow,
+        AppState::Idle => Color::Green,
+        AppState::Recording => Color::Red,
+        AppState::Transcribing => Color::Yellow,
+        AppState::Typing => Color::Green,
+        AppState::Muted => Color::DarkGray,
+        AppState::DeviceLost => Color::Magenta,
+        AppState::ModelFailed => Color::Red,
+    }
 }
 
 /// Render status line with VU meter
-fn render_status<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
-    let state_color = app.state.color();
-    let state_text = app.state.display_text();
+fn render_status(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
+    let state_color = color(app.status.state);
+    let state_text = app.status.state.display_text();
 
     // Split into status text and VU meter
     let chunks = Layout::default()
@@ -42,11 +67,14 @@ fn render_status<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateContai
         .split(area);
 
     // Status text
-    let status_text = if let Some(ref error) = app.error_message {
+    let mut status_text = if let Some(ref error) = app.status.error_message {
         format!("Status: {} | Error: {}", state_text, error)
     } else {
         format!("Status: {}", state_text)
     };
+    if let Some(warning) = app.level_warning {
+        status_text = format!("{} | {}", status_text, warning.display_text());
+    }
 
     let status = Paragraph::new(status_text)
         .style(Style::default().fg(state_color))
@@ -55,48 +83,227 @@ fn render_status<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateContai
     frame.render_widget(status, chunks[0]);
 
     // VU meter
-    let audio_percent = (app.audio_level * 100.0) as u16;
-    let vu_meter = Gauge::default()
-        .block(Block::default().borders(Borders::ALL))
-        .gauge_style(
-            Style::default()
-                .fg(Color::Green)
-                .bg(Color::Black)
-                .add_modifier(Modifier::BOLD),
-        )
-        .percent(audio_percent);
+    render_meter(frame, chunks[1], app);
+}
+
+/// Quietest level the meter shows (dBFS)
+const METER_FLOOR_DB: f32 = -60.0;
+/// Start of the red clipping zone on the meter (dBFS)
+const CLIP_ZONE_DB: f32 = -1.0;
+
+/// Level in dBFS, floored at the bottom of the meter
+fn dbfs(level: f32) -> f32 {
+    if level <= 0.0 {
+        return METER_FLOOR_DB;
+    }
+    (20.0 * level.log10()).max(METER_FLOOR_DB)
+}
 
-    frame.render_widget(vu_meter, chunks[1]);
+/// Position of a linear level on the meter's log scale (0.0-1.0)
+fn meter_position(level: f32) -> f32 {
+    (1.0 - dbfs(level) / METER_FLOOR_DB).clamp(0.0, 1.0)
 }
 
+/// Render the level meter and a sparkline of recent levels
+///
+/// The bar shows RMS in dBFS with a peak-hold marker, a red clipping zone and
+/// ticks at the voice (yellow) and silence (blue) thresholds.
+fn render_meter(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
+    let peak_hold = app.peak_hold();
+    let title = if dbfs(peak_hold) >= CLIP_ZONE_DB {
+        Span::styled("CLIP", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+    } else {
+        Span::raw(format!("Level {:.0} dBFS", dbfs(app.audio_level)))
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    // Level bar
+    let width = inner.width as usize;
+    let cell_of = |level: f32| {
+        ((meter_position(level) * width as f32) as usize).min(width.saturating_sub(1))
+    };
+    let level_cells = (meter_position(app.audio_level) * width as f32).round() as usize;
+    let peak_cell = cell_of(peak_hold);
+    let voice_cell = cell_of(app.voice_threshold);
+    let silence_cell = cell_of(app.silence_threshold);
+    let clip_cell = (meter_position(10f32.powf(CLIP_ZONE_DB / 20.0)) * width as f32) as usize;
+
+    let spans: Vec<Span> = (0..width)
+        .map(|cell| {
+            let in_clip_zone = cell >= clip_cell;
+            let lit = cell < level_cells;
+            let color = if in_clip_zone { Color::Red } else { Color::Green };
+            let background = if lit { color } else { Color::Black };
+
+            if cell == peak_cell && peak_hold > 0.0 {
+                Span::styled("│", Style::default().fg(Color::White).add_modifier(Modifier::BOLD))
+            } else if cell == voice_cell {
+                Span::styled("┃", Style::default().fg(Color::Yellow).bg(background))
+            } else if cell == silence_cell {
+                Span::styled("┃", Style::default().fg(Color::Blue).bg(background))
+            } else if lit {
+                Span::styled("█", Style::default().fg(color))
+            } else if in_clip_zone {
+                Span::styled("░", Style::default().fg(Color::Red))
+            } else {
+                Span::raw(" ")
+            }
+        })
+        .collect();
+
+    let bar = Paragraph::new(Line::from(spans)).style(Style::default().bg(Color::Black));
+    frame.render_widget(bar, rows[0]);
+
+    // Recent levels on the same scale, newest on the right
+    let history: Vec<u64> = app
+        .level_history
+        .iter()
+        .skip(app.level_history.len().saturating_sub(width))
+        .map(|&level| (meter_position(level) * 100.0) as u64)
+        .collect();
+    let sparkline = Sparkline::default()
+        .data(&history)
+        .max(100)
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(sparkline, rows[1]);
+}
+
+/// Marker in front of the selected history entry
+const HIGHLIGHT_SYMBOL: &str = "> ";
+
 /// Render transcription history
-fn render_history<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
+fn render_history(frame: &mut Frame, area: Rect, app: &mut AppStateContainer) {
+    // Inside the borders, minus room for the highlight marker
+    let width = (area.width as usize).saturating_sub(2 + HIGHLIGHT_SYMBOL.len()).max(1);
+    app.history_page = (area.height as usize).saturating_sub(2).max(1);
+
     let items: Vec<ListItem> = app
-        .history
-        .iter()
-        .map(|entry| {
-            let time = entry.format_time();
-            let content = format!("[{}] {}", time, entry.text);
-            ListItem::new(content)
-        })
+        .visible_history()
+        .map(|entry| history_item(entry, width))
         .collect();
 
+    let title = if app.editing_filter {
+        format!("History (filter: {}_)", app.history_filter)
+    } else if !app.history_filter.is_empty() {
+        format!("History (filter: {})", app.history_filter)
+    } else {
+        "History".to_string()
+    };
+
     let history_list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title("History"))
-        .style(Style::default().fg(Color::White));
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol(HIGHLIGHT_SYMBOL);
+
+    frame.render_stateful_widget(history_list, area, &mut app.history_state);
+}
+
+/// A history entry wrapped to `width` columns
+fn history_item(entry: &HistoryEntry, width: usize) -> ListItem<'static> {
+    let content = match entry.rejected {
+        Some(ref reason) => format!("[{}] {} ({})", entry.format_time(), entry.text, reason),
+        None => format!("[{}] {}", entry.format_time(), entry.text),
+    };
+    let mut lines: Vec<Line> = textwrap::wrap(&content, width)
+        .into_iter()
+        .map(|line| Line::from(line.into_owned()))
+        .collect();
+
+    if entry.rejected.is_some() {
+        return ListItem::new(lines).style(
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::DIM),
+        );
+    }
+
+    if let (Some(confidence), Some(last)) = (entry.confidence, lines.last_mut()) {
+        last.spans.push(Span::styled(
+            format!(" {:.0}%", confidence * 100.0),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    ListItem::new(lines)
+}
+
+/// Rows of the log pane, including its borders
+const LOG_PANE_HEIGHT: u16 = 10;
+
+/// Render the most recent diagnostic log lines, colored by level
+fn render_log(frame: &mut Frame, area: Rect, logs: &LogBuffer) {
+    let lines: Vec<Line> = logs
+        .tail(area.height.saturating_sub(2) as usize)
+        .into_iter()
+        .map(|line| {
+            // Lines look like "12:34:56  WARN message"
+            let color = match line.split_whitespace().nth(1) {
+                Some("ERROR") => Color::Red,
+                Some("WARN") => Color::Yellow,
+                Some("INFO") => Color::White,
+                _ => Color::DarkGray,
+            };
+            Line::styled(line, Style::default().fg(color))
+        })
+        .collect();
+
+    let log = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Log"));
+
+    frame.render_widget(log, area);
+}
+
+/// Render stage timings of the last transcription and the recent average
+fn render_stats(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
+    let label = Style::default().fg(Color::DarkGray);
+    let lines = match (app.stats.last(), app.stats.average()) {
+        (Some(last), Some(average)) => vec![
+            Line::from(vec![
+                Span::styled(format!("{:<8}", "Last"), label),
+                Span::raw(last.to_string()),
+            ]),
+            Line::from(vec![
+                Span::styled(format!("{:<8}", format!("Avg({})", app.stats.count())), label),
+                Span::raw(average.to_string()),
+            ]),
+        ],
+        _ => vec![Line::styled("No transcriptions yet", label)],
+    };
+
+    let stats = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Latency"));
 
-    frame.render_widget(history_list, area);
+    frame.render_widget(stats, area);
 }
 
 /// Render current text being processed
-fn render_current<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
-    let current_text = if app.current_text.is_empty() {
-        "_".to_string()
+fn render_current(frame: &mut Frame, area: Rect, app: &AppStateContainer) {
+    let current_line = if app.status.current_text.is_empty() && app.status.partial_text.is_empty() {
+        Line::from("_")
     } else {
-        app.current_text.clone()
+        let mut spans = vec![Span::raw(app.status.current_text.clone())];
+        if !app.status.partial_text.is_empty() {
+            if !app.status.current_text.is_empty() {
+                spans.push(Span::raw(" "));
+            }
+            spans.push(Span::styled(
+                app.status.partial_text.clone(),
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            ));
+        }
+        Line::from(spans)
     };
 
-    let current = Paragraph::new(current_text)
+    let current = Paragraph::new(current_line)
         .style(Style::default().fg(Color::Cyan))
         .block(Block::default().borders(Borders::ALL).title("Current"));
 
@@ -104,13 +311,30 @@ fn render_current<B: Backend>(frame: &mut Frame, area: Rect, app: &AppStateConta
 }
 
 /// Render control hints
-fn render_controls<B: Backend>(frame: &mut Frame, area: Rect) {
+fn render_controls(frame: &mut Frame, area: Rect) {
+    let key = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
     let controls = vec![
-        Span::styled("[M]", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        Span::styled("[M]", key),
         Span::raw("ute  "),
-        Span::styled("[C]", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        Span::styled("[C]", key),
         Span::raw("ancel  "),
-        Span::styled("[Q]", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        Span::styled("[↑↓]", key),
+        Span::raw(" Select  "),
+        Span::styled("[Enter]", key),
+        Span::raw(" Re-type  "),
+        Span::styled("[P]", key),
+        Span::raw("lay  "),
+        Span::styled("[Y]", key),
+        Span::raw("ank  "),
+        Span::styled("[D]", key),
+        Span::raw("elete  "),
+        Span::styled("[/]", key),
+        Span::raw(" Filter  "),
+        Span::styled("[S]", key),
+        Span::raw("tats  "),
+        Span::styled("[L]", key),
+        Span::raw("og  "),
+        Span::styled("[Q]", key),
         Span::raw("uit"),
     ];
 
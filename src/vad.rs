@@ -0,0 +1,124 @@
+use crate::config::AudioConfig;
+
+/// What a chunk of audio did to the voice activity detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    /// Level rose above the voice threshold; an utterance has begun
+    SpeechStarted,
+    /// Level dropped below the silence threshold during an utterance
+    SilenceStarted,
+    /// Silence lasted long enough after enough speech; the utterance is over
+    SpeechEnded,
+    /// Silence lasted long enough, but the utterance was too short to keep
+    TooShort,
+}
+
+/// RMS-based voice activity detector
+///
+/// Speech starts when a chunk's RMS rises above `voice_threshold` and ends
+/// once it has stayed below `silence_threshold` for `silence_duration`.
+/// Utterances shorter than `min_speech_duration`, not counting the closing
+/// silence, are dropped.
+///
+/// Time is measured in samples fed, not wall time, so the same input always
+/// produces the same events however fast it arrives.
+pub struct Vad {
+    voice_threshold: f32,
+    silence_threshold: f32,
+    /// Silence before stopping (samples)
+    silence_samples: usize,
+    /// Minimum utterance length, not counting the closing silence (samples)
+    min_speech_samples: usize,
+    /// Whether an utterance is in progress
+    active: bool,
+    /// Samples of the utterance so far
+    samples: usize,
+    /// Samples of the utterance whose chunk was above the silence threshold
+    voiced_samples: usize,
+    /// Samples since silence was first detected
+    trailing_silence_samples: usize,
+}
+
+impl Vad {
+    pub fn new(config: &AudioConfig) -> Self {
+        Self {
+            voice_threshold: config.voice_threshold,
+            silence_threshold: config.silence_threshold,
+            silence_samples: seconds_to_samples(config.silence_duration, config.sample_rate),
+            min_speech_samples: seconds_to_samples(config.min_speech_duration, config.sample_rate),
+            active: false,
+            samples: 0,
+            voiced_samples: 0,
+            trailing_silence_samples: 0,
+        }
+    }
+
+    /// Feed the RMS level of the next chunk of `len` samples
+    pub fn process(&mut self, rms: f32, len: usize) -> Option<VadEvent> {
+        if !self.active {
+            // Not in an utterance - check for voice activity
+            if rms > self.voice_threshold {
+                self.active = true;
+                self.samples = len;
+                self.voiced_samples = len;
+                self.trailing_silence_samples = 0;
+                return Some(VadEvent::SpeechStarted);
+            }
+            return None;
+        }
+
+        self.samples += len;
+        if rms >= self.silence_threshold {
+            // Voice still active, reset silence timer
+            self.voiced_samples += len;
+            self.trailing_silence_samples = 0;
+            return None;
+        }
+
+        let silence_began = self.trailing_silence_samples == 0;
+        self.trailing_silence_samples += len;
+        if silence_began {
+            return Some(VadEvent::SilenceStarted);
+        }
+        if self.trailing_silence_samples < self.silence_samples {
+            return None;
+        }
+
+        let speech_samples = self.samples - self.trailing_silence_samples;
+        let event = if speech_samples >= self.min_speech_samples {
+            VadEvent::SpeechEnded
+        } else {
+            VadEvent::TooShort
+        };
+        self.reset();
+        Some(event)
+    }
+
+    /// Whether an utterance is in progress
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Abandon the utterance in progress
+    pub fn reset(&mut self) {
+        self.active = false;
+    }
+
+    /// Samples of silence at the end of the utterance
+    pub fn trailing_silence(&self) -> usize {
+        self.trailing_silence_samples
+    }
+
+    /// Fraction of the utterance, minus trailing silence, that was voiced
+    pub fn voiced_ratio(&self) -> f32 {
+        let speech_samples = self.samples.saturating_sub(self.trailing_silence_samples);
+        if speech_samples == 0 {
+            return 0.0;
+        }
+        (self.voiced_samples as f32 / speech_samples as f32).min(1.0)
+    }
+}
+
+fn seconds_to_samples(seconds: f32, sample_rate: u32) -> usize {
+    (seconds.max(0.0) * sample_rate as f32).round() as usize
+}
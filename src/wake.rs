@@ -0,0 +1,227 @@
+use crate::audio::Recording;
+use crate::config::{WakeConfig, WhisperConfig};
+use crate::transcribe::{Transcriber, TranscriptionJob};
+use anyhow::Result;
+use std::path::Path;
+
+/// Shortest phrase word that may be misheard by a letter
+const FUZZY_WORD_LEN: usize = 4;
+
+/// The wake phrase, matched loosely against the start of a transcription
+#[derive(Debug, Clone)]
+pub struct WakePhrase {
+    words: Vec<String>,
+}
+
+impl WakePhrase {
+    pub fn new(phrase: &str) -> Self {
+        Self {
+            words: words(phrase).into_iter().map(|(word, _)| word).collect(),
+        }
+    }
+
+    /// The text after the wake phrase, if `text` opens with it
+    ///
+    /// Words match ignoring case and punctuation, and longer ones may be a
+    /// letter off, since whisper hears "hand" as "and" often enough. The rest
+    /// loses the punctuation that separated it from the phrase and starts
+    /// with a capital.
+    pub fn strip(&self, text: &str) -> Option<String> {
+        let heard = words(text);
+        if self.words.is_empty() || heard.len() < self.words.len() {
+            return None;
+        }
+        let matched = self
+            .words
+            .iter()
+            .zip(&heard)
+            .all(|(wanted, (word, _))| similar(word, wanted));
+        if !matched {
+            return None;
+        }
+
+        let (_, end) = heard[self.words.len() - 1];
+        let rest = text[end..].trim_start_matches(|c: char| {
+            c.is_whitespace() || matches!(c, ',' | '.' | '!' | '?' | ';' | ':' | '-' | '…')
+        });
+        let mut chars = rest.chars();
+        Some(match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        })
+    }
+}
+
+/// Words of `text`, lowercased, each with the byte offset just past it
+fn words(text: &str) -> Vec<(String, usize)> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for (offset, c) in text.char_indices() {
+        if c.is_alphanumeric() || c == '\'' {
+            current.extend(c.to_lowercase());
+        } else if !current.is_empty() {
+            words.push((std::mem::take(&mut current), offset));
+        }
+    }
+    if !current.is_empty() {
+        words.push((current, text.len()));
+    }
+    words
+}
+
+/// Whether a heard word passes for a wanted one
+fn similar(heard: &str, wanted: &str) -> bool {
+    heard == wanted || (wanted.chars().count() >= FUZZY_WORD_LEN && edit_distance(heard, wanted) <= 1)
+}
+
+/// Levenshtein distance in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Checks whether utterances open with the wake phrase
+///
+/// A whisper pass over just the first couple of seconds, prompted with the
+/// phrase, is cheaper than transcribing everything said in the room. With
+/// the whisper.cpp backend each pass loads its model again, so the saving is
+/// small unless the check has a small model of its own like
+/// `ggml-tiny.en.bin`. Utterances short enough to be heard whole by a check
+/// sharing the main model are only transcribed once.
+pub struct Spotter {
+    phrase: WakePhrase,
+    transcriber: Transcriber,
+    listen_seconds: f32,
+    /// Whether the check transcribes just like the main pass would
+    like_main: bool,
+}
+
+impl Spotter {
+    /// Prepare the check, sharing `main`'s model unless `wake.model_path`
+    /// names another; for the in-process backend that one is loaded here
+    pub fn load(main: &Transcriber, whisper: &WhisperConfig, wake: &WakeConfig) -> Result<Self> {
+        let mut config = whisper.clone();
+        let like_main = wake.model_path.is_empty() && !whisper.translate && whisper.beam_size.is_none();
+        // The prompt nudges whisper to spell the phrase the way it is matched
+        config.initial_prompt = Some(wake.phrase.clone());
+        config.translate = false;
+        config.beam_size = None;
+
+        let transcriber = if wake.model_path.is_empty() {
+            main.with_config(&config)
+        } else {
+            config.model_path = wake.model_path.clone();
+            Transcriber::load(&config)?
+        };
+
+        Ok(Self {
+            phrase: WakePhrase::new(&wake.phrase),
+            transcriber,
+            listen_seconds: wake.listen_seconds,
+            like_main,
+        })
+    }
+
+    pub fn phrase(&self) -> &WakePhrase {
+        &self.phrase
+    }
+
+    /// Start checking the opening of `recording`
+    ///
+    /// Returns the opening as its own recording, to discard once the job is done.
+    pub fn start(&self, recording: &Recording, wav_dir: Option<&Path>) -> Result<(Recording, TranscriptionJob)> {
+        let len = self.opening_len(recording).min(recording.samples.len());
+        let opening = recording.samples[..len].to_vec();
+        let clip = Recording::from_samples(opening, recording.sample_rate, wav_dir)?;
+        match self.transcriber.start(&clip) {
+            Ok(job) => Ok((clip, job)),
+            Err(e) => {
+                clip.discard();
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the check hears all of `recording` as the main pass would, so
+    /// its transcription can stand in for the full one
+    pub fn covers(&self, recording: &Recording) -> bool {
+        self.like_main && recording.samples.len() <= self.opening_len(recording)
+    }
+
+    /// Samples of `recording` the check listens to
+    fn opening_len(&self, recording: &Recording) -> usize {
+        (self.listen_seconds * recording.sample_rate as f32) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(text: &str) -> Option<String> {
+        WakePhrase::new("Hey hand").strip(text)
+    }
+
+    #[test]
+    fn phrase_is_matched_ignoring_case_and_punctuation() {
+        assert_eq!(strip("Hey, Hand! open the door."), Some("Open the door.".to_string()));
+        assert_eq!(strip("hey hand... what's next?"), Some("What's next?".to_string()));
+        assert_eq!(strip("HEY HAND - éclair"), Some("Éclair".to_string()));
+        assert_eq!(strip("Open the door, hey hand."), None);
+    }
+
+    #[test]
+    fn longer_words_may_be_a_letter_off() {
+        assert_eq!(strip("Hey and, send it."), Some("Send it.".to_string()));
+        assert_eq!(strip("Hey hands, send it."), Some("Send it.".to_string()));
+        assert_eq!(strip("Hey the, send it."), None);
+        assert_eq!(strip("Hey hold, send it."), None);
+    }
+
+    #[test]
+    fn short_words_must_match_exactly() {
+        assert_eq!(strip("Hay hand, send it."), None);
+        assert_eq!(strip("He hand, send it."), None);
+        assert_eq!(WakePhrase::new("ok go").strip("OK, go home"), Some("Home".to_string()));
+        assert_eq!(WakePhrase::new("ok go").strip("OK, so home"), None);
+    }
+
+    #[test]
+    fn phrase_alone_leaves_nothing() {
+        assert_eq!(strip("Hey hand."), Some(String::new()));
+        assert_eq!(strip("Hey hand"), Some(String::new()));
+        assert_eq!(strip("Hey"), None);
+        assert_eq!(strip(""), None);
+        assert_eq!(WakePhrase::new("...").strip("Anything"), None);
+    }
+
+    #[test]
+    fn similar_allows_one_edit_on_long_words_only() {
+        assert!(similar("hand", "hand"));
+        assert!(similar("and", "hand"));
+        assert!(similar("hant", "hand"));
+        assert!(!similar("the", "hand"));
+        assert!(!similar("hay", "hey"));
+        assert!(!similar("", "hand"));
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("hand", "hand"), 0);
+        assert_eq!(edit_distance("and", "hand"), 1);
+        assert_eq!(edit_distance("hand", "and"), 1);
+        assert_eq!(edit_distance("the", "hand"), 4);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+}
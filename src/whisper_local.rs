@@ -0,0 +1,174 @@
+use crate::audio::Recording;
+use crate::config::WhisperConfig;
+use crate::transcribe::{non_empty, timeout_for, RawSegment, TranscribeError, Transcription};
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+/// A GGML model loaded once and shared by every transcription
+pub struct LocalModel {
+    context: Arc<WhisperContext>,
+    config: WhisperConfig,
+}
+
+impl LocalModel {
+    /// Load the configured model for CPU inference
+    pub fn load(config: &WhisperConfig) -> Result<Self> {
+        let mut params = WhisperContextParameters::default();
+        params.use_gpu(false);
+
+        let context = WhisperContext::new_with_params(&config.model_path, params)
+            .context(format!("Failed to load whisper model at {}", config.model_path))?;
+
+        Ok(Self {
+            context: Arc::new(context),
+            config: config.clone(),
+        })
+    }
+
+    /// The same loaded model, decoding with `config`'s settings
+    pub fn with_config(&self, config: &WhisperConfig) -> Self {
+        Self {
+            context: self.context.clone(),
+            config: config.clone(),
+        }
+    }
+
+    /// Transcribe a recording's in-memory samples on a worker thread
+    pub fn start(&self, recording: &Recording) -> LocalJob {
+        let context = self.context.clone();
+        let config = self.config.clone();
+        let samples = recording.samples.clone();
+        let timeout = timeout_for(&self.config, recording.duration());
+        let started = Instant::now();
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let worker_abort = abort.clone();
+        let handle = thread::spawn(move || {
+            run(&context, &config, &samples, move || {
+                worker_abort.load(Ordering::Relaxed) || started.elapsed() >= timeout
+            })
+        });
+
+        LocalJob {
+            handle: Some(handle),
+            abort,
+            started,
+            timeout,
+        }
+    }
+}
+
+/// An in-process transcription running on a worker thread
+pub struct LocalJob {
+    handle: Option<JoinHandle<Result<Transcription>>>,
+    abort: Arc<AtomicBool>,
+    started: Instant,
+    timeout: Duration,
+}
+
+impl LocalJob {
+    pub fn poll(&mut self) -> Option<Result<Transcription, TranscribeError>> {
+        let handle = self.handle.as_ref()?;
+
+        if !handle.is_finished() {
+            if self.started.elapsed() < self.timeout {
+                return None;
+            }
+            // The abort callback stops whisper shortly; don't wait for it
+            self.abort.store(true, Ordering::Relaxed);
+            self.handle = None;
+            return Some(Err(TranscribeError::Timeout(self.timeout.as_secs_f32())));
+        }
+
+        let result = self.handle.take()?.join().unwrap_or_else(|_| {
+            Err(anyhow::anyhow!("Whisper inference thread panicked"))
+        });
+        Some(result.map_err(TranscribeError::from).and_then(non_empty))
+    }
+
+    pub fn cancel(&mut self) -> TranscribeError {
+        self.abort.store(true, Ordering::Relaxed);
+        self.handle = None;
+        TranscribeError::Cancelled
+    }
+}
+
+impl Drop for LocalJob {
+    fn drop(&mut self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run whisper over 16kHz mono samples
+fn run(
+    context: &WhisperContext,
+    config: &WhisperConfig,
+    samples: &[f32],
+    should_abort: impl FnMut() -> bool + 'static,
+) -> Result<Transcription> {
+    let mut state = context
+        .create_state()
+        .context("Failed to create whisper state")?;
+
+    let strategy = match config.beam_size {
+        Some(beam_size) => SamplingStrategy::BeamSearch {
+            beam_size: beam_size as i32,
+            patience: -1.0,
+        },
+        None => SamplingStrategy::Greedy { best_of: 1 },
+    };
+
+    let mut params = FullParams::new(strategy);
+    params.set_language(Some(config.language.as_str()));
+    params.set_translate(config.translate);
+    if let Some(ref prompt) = config.initial_prompt {
+        params.set_initial_prompt(prompt);
+    }
+    if let Some(threads) = config.threads {
+        params.set_n_threads(threads as i32);
+    }
+    if let Some(temperature) = config.temperature {
+        params.set_temperature(temperature);
+    }
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_special(false);
+    params.set_print_timestamps(false);
+    params.set_abort_callback_safe(should_abort);
+
+    state
+        .full(params, samples)
+        .context("Whisper inference failed")?;
+
+    let mut segments = Vec::new();
+    let n_segments = state.full_n_segments().context("Failed to read segments")?;
+    for segment in 0..n_segments {
+        let mut token_probs = Vec::new();
+        for token in 0..state.full_n_tokens(segment)? {
+            let text = state.full_get_token_text_lossy(segment, token)?;
+            // Special tokens like "[_BEG_]" or "[_TT_150]" carry no speech
+            if !text.starts_with("[_") {
+                token_probs.push(state.full_get_token_prob(segment, token)?);
+            }
+        }
+
+        // Segment times are reported in 10ms units
+        segments.push(RawSegment {
+            end: state.full_get_segment_t1(segment)? as f32 / 100.0,
+            text: state.full_get_segment_text_lossy(segment)?,
+            token_probs,
+        });
+    }
+
+    let language = state
+        .full_lang_id_from_state()
+        .ok()
+        .and_then(whisper_rs::get_lang_str)
+        .map(str::to_string);
+
+    Ok(Transcription::from_segments(segments, language))
+}
@@ -0,0 +1,143 @@
+//! End-to-end harness: the real pipeline, fed from a WAV file, transcribing
+//! with `tests/fake-whisper` and typing into a [`RecordingSink`]
+
+use std::f32::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thehand::history::{HistoryRecord, HistoryStore};
+use thehand::status::{AppState, Status};
+use thehand::{audio, Config, FileSource, OutputSink, Pipeline, PipelineEvent};
+
+pub const RATE: u32 = 16000;
+
+/// Window title the sink reports, so tests can see it reach the history
+pub const WINDOW: &str = "Test window";
+
+/// Keeps whatever would have been typed
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    typed: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingSink {
+    pub fn typed(&self) -> Vec<String> {
+        self.typed.lock().unwrap().clone()
+    }
+}
+
+impl OutputSink for RecordingSink {
+    fn type_text(&mut self, text: &str) -> anyhow::Result<()> {
+        self.typed.lock().unwrap().push(text.to_string());
+        Ok(())
+    }
+
+    fn focused_window(&self) -> Option<String> {
+        Some(WINDOW.to_string())
+    }
+}
+
+/// One test's config, scratch directory and sink
+pub struct Harness {
+    pub dir: PathBuf,
+    pub config: Config,
+    pub sink: RecordingSink,
+}
+
+impl Harness {
+    /// `replies` script fake-whisper, one line per transcription
+    pub fn new(name: &str, replies: &[&str]) -> Self {
+        let dir = std::env::temp_dir()
+            .join("thehand-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let model = dir.join("model");
+        let script: String = replies.iter().map(|reply| format!("{}\n", reply)).collect();
+        fs::write(&model, script).unwrap();
+
+        let mut config = Config::default();
+        config.whisper.binary_path =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake-whisper").to_string();
+        config.whisper.model_path = model.to_string_lossy().into_owned();
+        config.ui.log_to_file = false;
+        config.history.path = dir.join("history.jsonl").to_string_lossy().into_owned();
+
+        Self {
+            dir,
+            config,
+            sink: RecordingSink::default(),
+        }
+    }
+
+    /// Run the pipeline over `samples` until `transcriptions` have finished
+    ///
+    /// Typed or rejected results, failures and utterances without the wake
+    /// phrase all count as finished.
+    pub fn run(&self, samples: &[f32], transcriptions: usize) -> Status {
+        let mut pipeline = self.start(samples);
+        self.drive(&mut pipeline, transcriptions)
+    }
+
+    /// Build a pipeline that reads `samples` as a WAV file
+    pub fn start(&self, samples: &[f32]) -> Pipeline {
+        let wav = self.dir.join("input.wav");
+        audio::write_wav(&wav, samples, RATE).unwrap();
+
+        Pipeline::builder(self.config.clone())
+            .source(FileSource::open(&wav, false).unwrap())
+            .sink(self.sink.clone())
+            .build()
+            .unwrap()
+    }
+
+    /// Poll `pipeline` until `transcriptions` have finished
+    pub fn drive(&self, pipeline: &mut Pipeline, transcriptions: usize) -> Status {
+        let mut status = Status::new();
+        status.set_state(AppState::LoadingModel);
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let mut finished = 0;
+        while finished < transcriptions {
+            assert!(Instant::now() < deadline, "pipeline didn't finish in time");
+            for event in pipeline.poll() {
+                if matches!(
+                    event,
+                    PipelineEvent::Transcribed(_) | PipelineEvent::Failed(_) | PipelineEvent::Ignored
+                ) {
+                    finished += 1;
+                }
+                status.handle_event(&event, &self.config);
+            }
+            pipeline.metrics().enter_state(status.state);
+            thread::sleep(Duration::from_millis(10));
+        }
+        status
+    }
+
+    pub fn typed(&self) -> Vec<String> {
+        self.sink.typed()
+    }
+
+    pub fn history(&self) -> Vec<HistoryRecord> {
+        HistoryStore::open(&self.config.history.path).load().unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A second of tone, then enough silence for the VAD to end the recording
+pub fn utterance() -> Vec<f32> {
+    let mut samples: Vec<f32> = (0..RATE)
+        .map(|i| 0.1 * (2.0 * PI * 440.0 * i as f32 / RATE as f32).sin())
+        .collect();
+    samples.resize(samples.len() + (RATE as usize * 5) / 2, 0.0);
+    samples
+}
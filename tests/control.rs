@@ -0,0 +1,69 @@
+//! Commands sent to a running instance over its control socket
+
+use std::fs;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use thehand::control::{self, ControlCommand, ControlSocket};
+
+fn socket(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("thehand-tests");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}-{}.sock", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn cancel_reaches_the_listener_and_is_acknowledged() {
+    let path = socket("control");
+    // Left behind by a process that crashed without cleaning up
+    drop(UnixListener::bind(&path).unwrap());
+    let listener = ControlSocket::bind(&path).unwrap();
+    assert!(ControlSocket::bind(&path).is_err(), "a second listener took over the socket");
+
+    let client = {
+        let path = path.clone();
+        thread::spawn(move || control::send(&path, ControlCommand::Cancel))
+    };
+    let mut received = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while received.is_empty() && Instant::now() < deadline {
+        received = listener.poll();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(received, vec![ControlCommand::Cancel]);
+    client.join().unwrap().unwrap();
+
+    drop(listener);
+    assert!(!path.exists(), "socket left behind");
+    assert!(control::send(&path, ControlCommand::Cancel).is_err());
+}
+
+#[test]
+fn silent_client_does_not_hold_up_polling() {
+    let path = socket("silent");
+    let listener = ControlSocket::bind(&path).unwrap();
+    // Connects and never says anything
+    let silent = UnixStream::connect(&path).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let started = Instant::now();
+    assert!(listener.poll().is_empty());
+    assert!(started.elapsed() < Duration::from_millis(100), "poll waited on the client");
+
+    drop(silent);
+    let client = {
+        let path = path.clone();
+        thread::spawn(move || control::send(&path, ControlCommand::Cancel))
+    };
+    let mut received = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while received.is_empty() && Instant::now() < deadline {
+        received = listener.poll();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(received, vec![ControlCommand::Cancel]);
+    client.join().unwrap().unwrap();
+}
@@ -0,0 +1,128 @@
+//! Recovery from input devices that fail to open, error out or stall
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use thehand::config::{AudioConfig, Config};
+use thehand::source::{PcmFormat, SampleCallback};
+use thehand::{AudioCapture, AudioEvent, AudioSource, PcmSource};
+
+const RATE: u32 = 16000;
+
+/// A device that can't be opened the first time and never delivers after
+struct DeadDevice {
+    starts: Arc<AtomicUsize>,
+    /// Held like a stream would hold it, but never called
+    callback: Option<SampleCallback>,
+}
+
+impl AudioSource for DeadDevice {
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn start(&mut self, callback: SampleCallback) -> anyhow::Result<()> {
+        if self.starts.fetch_add(1, Ordering::Relaxed) == 0 {
+            anyhow::bail!("No input device available");
+        }
+        self.callback = Some(callback);
+        Ok(())
+    }
+
+    fn can_restart(&self) -> bool {
+        true
+    }
+
+    fn stop(&mut self) {
+        self.callback = None;
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.callback.as_ref().map(|_| "Dead mic".to_string())
+    }
+}
+
+/// Supervise `capture` until `count` events other than level updates arrive
+fn events(capture: &mut AudioCapture, count: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut seen = Vec::new();
+    while seen.len() < count {
+        assert!(Instant::now() < deadline, "only saw {:?}", seen);
+        capture.supervise();
+        while let Some(event) = capture.poll_event() {
+            match event {
+                AudioEvent::Error(msg) => seen.push(format!("error: {}", msg)),
+                AudioEvent::DeviceLost => seen.push("lost".to_string()),
+                AudioEvent::DeviceRestored(name) => seen.push(format!("restored: {}", name)),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    seen
+}
+
+#[test]
+fn lost_device_is_reopened_and_watched_for_stalls() {
+    let config = AudioConfig {
+        stall_timeout: 0.2,
+        ..Config::default().audio
+    };
+    let starts = Arc::new(AtomicUsize::new(0));
+    let source = DeadDevice {
+        starts: starts.clone(),
+        callback: None,
+    };
+
+    // Failing to open at startup isn't fatal for a device
+    let mut capture = AudioCapture::with_source(Box::new(source), &config, None).unwrap();
+    assert_eq!(
+        events(&mut capture, 3),
+        ["error: No input device available", "lost", "restored: Dead mic"]
+    );
+
+    // Then it goes quiet, and is reopened again
+    assert_eq!(
+        events(&mut capture, 3),
+        ["error: No audio from the input device for 0.2s", "lost", "restored: Dead mic"]
+    );
+    assert_eq!(starts.load(Ordering::Relaxed), 3);
+}
+
+/// Delivers some audio, then fails like a pipe whose writer crashed
+struct BrokenPipe {
+    left: usize,
+}
+
+impl std::io::Read for BrokenPipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.left == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "writer went away"));
+        }
+        let n = buf.len().min(self.left);
+        buf[..n].fill(0);
+        self.left -= n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn pipe_read_error_is_reported() {
+    let source = PcmSource::new(BrokenPipe { left: 3200 }, PcmFormat::S16le, RATE);
+    let mut capture = AudioCapture::with_source(Box::new(source), &Config::default().audio, None).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut errors = Vec::new();
+    while !capture.is_finished() {
+        assert!(Instant::now() < deadline, "source never finished");
+        thread::sleep(Duration::from_millis(10));
+    }
+    capture.supervise();
+    while let Some(event) = capture.poll_event() {
+        if let AudioEvent::Error(msg) = event {
+            errors.push(msg);
+        }
+    }
+    assert_eq!(errors, ["Failed to read audio input: writer went away"]);
+}
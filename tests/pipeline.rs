@@ -0,0 +1,319 @@
+//! End-to-end pipeline tests against a fake whisper.cpp
+
+mod common;
+
+use common::{utterance, Harness, WINDOW};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use thehand::config::{PostProcessStep, TranscriptionBackend};
+use thehand::status::{AppState, Status};
+use thehand::PipelineEvent;
+
+#[test]
+fn accepted_text_is_typed_and_saved() {
+    let harness = Harness::new("accepted", &["text Hello world."]);
+    let status = harness.run(&utterance(), 1);
+
+    assert_eq!(harness.typed(), ["Hello world."]);
+    assert_eq!(status.state, AppState::Idle);
+    assert_eq!(status.error_message, None);
+
+    let history = harness.history();
+    assert_eq!(history.len(), 1);
+    let record = &history[0];
+    assert_eq!(record.text, "Hello world.");
+    assert_eq!(record.raw_text, "Hello world.");
+    assert!(record.typed);
+    assert_eq!(record.rejected, None);
+    assert_eq!(record.window.as_deref(), Some(WINDOW));
+    assert_eq!(record.model, "model");
+    assert_eq!(record.language.as_deref(), Some("en"));
+    assert!((record.duration - 3.0).abs() < 0.05, "duration {}", record.duration);
+
+    let timings = &record.timings;
+    assert!((timings.silence_wait - 2.0).abs() < 0.05, "silence wait {}", timings.silence_wait);
+    assert!(timings.transcription > 0.0);
+    assert!((timings.real_time_factor - timings.transcription / record.duration).abs() < 1e-3);
+    // Latency runs from once the WAV is written
+    assert!(timings.total() <= record.latency + timings.silence_wait + timings.wav_write + 0.01);
+}
+
+#[test]
+fn post_processing_changes_typed_text_only() {
+    let mut harness = Harness::new("postprocess", &["text Deploy to staging."]);
+    harness.config.postprocess = vec![
+        PostProcessStep::StripTrailingPeriod,
+        PostProcessStep::LowercaseFirst,
+    ];
+    harness.run(&utterance(), 1);
+
+    assert_eq!(harness.typed(), ["deploy to staging"]);
+    let history = harness.history();
+    assert_eq!(history[0].text, "deploy to staging");
+    assert_eq!(history[0].raw_text, "Deploy to staging.");
+}
+
+#[test]
+fn hallucination_is_rejected_but_kept_in_history() {
+    let harness = Harness::new("hallucination", &["text Thank you for watching."]);
+    let status = harness.run(&utterance(), 1);
+
+    assert!(harness.typed().is_empty());
+    assert_eq!(status.state, AppState::Idle);
+    let history = harness.history();
+    assert_eq!(history.len(), 1);
+    assert!(!history[0].typed);
+    assert_eq!(history[0].rejected.as_deref(), Some("known hallucination"));
+}
+
+#[test]
+fn whisper_failure_is_shown() {
+    let harness = Harness::new("failure", &["fail model exploded"]);
+    let status = harness.run(&utterance(), 1);
+
+    assert!(harness.typed().is_empty());
+    assert!(harness.history().is_empty());
+    assert_eq!(status.state, AppState::Idle);
+    assert_eq!(
+        status.error_message.as_deref(),
+        Some("Transcription failed: Whisper.cpp failed: model exploded")
+    );
+}
+
+#[test]
+fn model_load_failure_is_shown_and_speech_ignored() {
+    let mut harness = Harness::new("load-failure", &["text Never heard."]);
+    // Without the feature this can't load; with it, the script isn't a model
+    harness.config.whisper.backend = TranscriptionBackend::WhisperRs;
+    let mut pipeline = harness.start(&utterance());
+    let mut status = Status::new();
+    status.set_state(AppState::LoadingModel);
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut load_failed = false;
+    while !(load_failed && pipeline.source_finished()) {
+        assert!(Instant::now() < deadline, "pipeline didn't finish in time");
+        for event in pipeline.poll() {
+            load_failed |= matches!(event, PipelineEvent::ModelFailed(_));
+            status.handle_event(&event, &harness.config);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(harness.typed().is_empty());
+    assert!(harness.history().is_empty());
+    assert_eq!(status.state, AppState::ModelFailed);
+    assert!(status
+        .error_message
+        .as_deref()
+        .is_some_and(|message| message.starts_with("Failed to load the model: ")));
+}
+
+#[test]
+fn muting_during_a_transcription_stays_muted() {
+    let harness = Harness::new("mute", &["text Finish this."]);
+    let mut pipeline = harness.start(&utterance());
+    let mut status = Status::new();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut transcribed = false;
+    while !transcribed {
+        assert!(Instant::now() < deadline, "pipeline didn't finish in time");
+        for event in pipeline.poll() {
+            status.handle_event(&event, &harness.config);
+            match event {
+                PipelineEvent::RecordingStopped { .. } => pipeline.set_muted(true),
+                PipelineEvent::Transcribed(_) => transcribed = true,
+                _ => {}
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // The utterance already heard is still typed
+    assert_eq!(harness.typed(), ["Finish this."]);
+    assert_eq!(status.state, AppState::Muted);
+    assert!(pipeline.is_muted());
+
+    pipeline.set_muted(false);
+    for event in pipeline.poll() {
+        status.handle_event(&event, &harness.config);
+    }
+    assert_eq!(status.state, AppState::Idle);
+}
+
+#[test]
+fn unparseable_output_is_shown() {
+    let harness = Harness::new("garbage", &["garbage"]);
+    let status = harness.run(&utterance(), 1);
+
+    assert!(harness.typed().is_empty());
+    let error = status.error_message.expect("error shown");
+    assert!(
+        error.starts_with("Transcription failed: Failed to parse whisper JSON output"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn hung_whisper_times_out() {
+    let mut harness = Harness::new("hang", &["hang"]);
+    harness.config.whisper.timeout_secs = 1.0;
+    harness.config.whisper.timeout_scale = 0.0;
+    let status = harness.run(&utterance(), 1);
+
+    assert!(harness.typed().is_empty());
+    assert!(harness.history().is_empty());
+    assert_eq!(status.error_message.as_deref(), Some("Transcription timed out after 1s"));
+}
+
+#[test]
+fn queued_utterances_are_typed_in_order() {
+    let harness = Harness::new("queue", &["text First one.", "text Second one.", "fail busy"]);
+    let audio: Vec<f32> = (0..3).flat_map(|_| utterance()).collect();
+    let status = harness.run(&audio, 3);
+
+    assert_eq!(harness.typed(), ["First one.", "Second one."]);
+    let texts: Vec<String> = harness.history().into_iter().map(|record| record.text).collect();
+    assert_eq!(texts, ["First one.", "Second one."]);
+
+    // The last failure's message on show
+    assert_eq!(
+        status.error_message.as_deref(),
+        Some("Transcription failed: Whisper.cpp failed: busy")
+    );
+}
+
+#[test]
+fn recordings_are_kept_with_their_history_entry() {
+    let mut harness = Harness::new("archive", &["text Keep this."]);
+    harness.config.audio.keep_recordings = true;
+    harness.config.audio.archive_dir = harness.dir.join("recordings").to_string_lossy().into_owned();
+    // Dropping the pipeline waits for the archive to finish
+    harness.run(&utterance(), 1);
+
+    let history = harness.history();
+    let kept = history[0].audio.as_deref().expect("no recording kept");
+    let (samples, rate) = thehand::archive::load(kept).unwrap();
+    assert_eq!(rate, common::RATE);
+    assert!((samples.len() as f32 / rate as f32 - history[0].duration).abs() < 0.01);
+}
+
+#[test]
+fn history_can_be_disabled() {
+    let mut harness = Harness::new("no-history", &["text Not saved."]);
+    harness.config.history.enabled = false;
+    harness.run(&utterance(), 1);
+
+    assert_eq!(harness.typed(), ["Not saved."]);
+    assert!(harness.history().is_empty());
+}
+
+#[test]
+fn only_speech_opening_with_the_wake_phrase_is_typed() {
+    let mut harness = Harness::new(
+        "wake",
+        &["text Hey hand, open the pod bay doors.", "text Hey, and make it quick."],
+    );
+    // The check has its own model, so its replies are scripted apart
+    let wake_model = harness.dir.join("wake-model");
+    fs::write(&wake_model, "text Hey hand, open\ntext What's for lunch?\ntext Hey, and make\n").unwrap();
+    harness.config.wake.enabled = true;
+    harness.config.wake.model_path = wake_model.to_string_lossy().into_owned();
+
+    let audio: Vec<f32> = (0..3).flat_map(|_| utterance()).collect();
+    let status = harness.run(&audio, 3);
+
+    // "and" passes for a misheard "hand"
+    assert_eq!(harness.typed(), ["Open the pod bay doors.", "Make it quick."]);
+    assert_eq!(status.state, AppState::Idle);
+
+    let history = harness.history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].raw_text, "Hey hand, open the pod bay doors.");
+    assert!(history[0].timings.wake > 0.0);
+}
+
+#[test]
+fn short_utterance_heard_whole_by_the_wake_check_is_transcribed_once() {
+    let mut harness = Harness::new("wake-whole", &["text Hey hand, lights on.", "text Never transcribed."]);
+    harness.config.wake.enabled = true;
+    harness.config.wake.listen_seconds = 10.0;
+
+    let status = harness.run(&utterance(), 1);
+
+    assert_eq!(harness.typed(), ["Lights on."]);
+    assert_eq!(status.state, AppState::Idle);
+    let runs = fs::read_to_string(format!("{}.calls", harness.config.whisper.model_path)).unwrap();
+    assert_eq!(runs.lines().count(), 1);
+
+    let history = harness.history();
+    assert_eq!(history[0].raw_text, "Hey hand, lights on.");
+    assert_eq!(history[0].timings.wake, 0.0);
+    assert!(history[0].timings.transcription > 0.0);
+}
+
+#[test]
+fn wake_check_hearing_no_words_is_ignored() {
+    let mut harness = Harness::new("wake-empty", &["text Never transcribed."]);
+    let wake_model = harness.dir.join("wake-model");
+    fs::write(&wake_model, "empty\n").unwrap();
+    harness.config.wake.enabled = true;
+    harness.config.wake.model_path = wake_model.to_string_lossy().into_owned();
+
+    let mut pipeline = harness.start(&utterance());
+    let status = harness.drive(&mut pipeline, 1);
+
+    assert!(harness.typed().is_empty());
+    assert!(harness.history().is_empty());
+    assert_eq!(status.state, AppState::Idle);
+    assert_eq!(status.error_message, None);
+    let metrics = pipeline.metrics().snapshot();
+    assert_eq!(metrics.utterances["ignored"], 1);
+    assert_eq!(metrics.utterances["failed"], 0);
+    assert_eq!(metrics.errors["transcription"], 0);
+}
+
+/// Response body of an HTTP GET
+fn get(address: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    response.split_once("\r\n\r\n").unwrap().1.to_string()
+}
+
+#[test]
+fn metrics_endpoint_counts_outcomes() {
+    let mut harness = Harness::new(
+        "metrics",
+        &["text Typed.", "text Thank you for watching.", "fail broken"],
+    );
+    harness.config.metrics.enabled = true;
+    harness.config.metrics.address = "127.0.0.1:0".to_string();
+    let audio: Vec<f32> = (0..3).flat_map(|_| utterance()).collect();
+    let mut pipeline = harness.start(&audio);
+    harness.drive(&mut pipeline, 3);
+    let address = pipeline.metrics_address().expect("metrics endpoint running");
+
+    let text = get(address, "/metrics");
+    assert!(text.contains("thehand_utterances_total{outcome=\"typed\"} 1\n"), "{}", text);
+    assert!(text.contains("thehand_utterances_total{outcome=\"rejected\"} 1\n"));
+    assert!(text.contains("thehand_utterances_total{outcome=\"failed\"} 1\n"));
+    assert!(text.contains("thehand_errors_total{kind=\"transcription\"} 1\n"));
+    assert!(text.contains("thehand_stage_seconds_count{stage=\"transcription\"} 2\n"));
+    assert!(text.contains("thehand_stage_seconds_count{stage=\"typing\"} 1\n"));
+    assert!(text.ends_with("# EOF\n"));
+
+    let json: serde_json::Value = serde_json::from_str(&get(address, "/metrics.json")).unwrap();
+    assert_eq!(json["utterances"]["typed"], 1);
+    assert_eq!(json["errors"]["timeout"], 0);
+    assert_eq!(json["state"], "idle");
+    assert!(json["state_seconds"]["recording"].as_f64().unwrap() > 0.0);
+    assert!(json["audio_seconds"].as_f64().unwrap() > 5.9);
+}
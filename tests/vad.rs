@@ -0,0 +1,278 @@
+//! VAD regression tests, driven by synthetic audio instead of a sound card
+
+use std::thread;
+use std::time::Duration;
+use thehand::config::{AudioConfig, Config};
+use thehand::source::Signal;
+use thehand::{AudioCapture, AudioEvent, SyntheticSource, Vad, VadEvent};
+
+const RATE: u32 = 16000;
+/// Samples per chunk the synthetic source delivers (10ms)
+const CHUNK: usize = 160;
+
+/// Default audio settings; every clean-up stage is off, so levels are exactly as generated
+fn config() -> AudioConfig {
+    Config::default().audio
+}
+
+/// Events without the level updates, reduced to what the tests compare
+#[derive(Debug, PartialEq)]
+enum Seen {
+    Voice,
+    Started,
+    Silence,
+    /// Recording length in samples
+    Stopped(usize),
+    Error(String),
+}
+
+fn run(config: &AudioConfig, signals: &[Signal]) -> Vec<Seen> {
+    let source = SyntheticSource::new(config.sample_rate, signals);
+    let capture = AudioCapture::with_source(Box::new(source), config, None).unwrap();
+
+    let mut seen = Vec::new();
+    while !capture.is_finished() {
+        drain(&capture, &mut seen);
+        thread::sleep(Duration::from_millis(1));
+    }
+    drain(&capture, &mut seen);
+    seen
+}
+
+fn drain(capture: &AudioCapture, seen: &mut Vec<Seen>) {
+    while let Some(event) = capture.poll_event() {
+        match event {
+            AudioEvent::Level { .. } => {}
+            AudioEvent::VoiceDetected => seen.push(Seen::Voice),
+            AudioEvent::RecordingStarted => seen.push(Seen::Started),
+            AudioEvent::SilenceDetected => seen.push(Seen::Silence),
+            AudioEvent::RecordingStopped(recording) => {
+                seen.push(Seen::Stopped(recording.samples.len()))
+            }
+            AudioEvent::Error(msg) => seen.push(Seen::Error(msg)),
+            other => panic!("synthetic sources have no device, yet got {:?}", other),
+        }
+    }
+}
+
+fn tone(amplitude: f32, seconds: f32) -> Signal {
+    Signal::Tone {
+        frequency: 440.0,
+        amplitude,
+        seconds,
+    }
+}
+
+fn samples(seconds: f32) -> usize {
+    (seconds * RATE as f32).round() as usize
+}
+
+#[test]
+fn tone_is_recorded_until_silence_runs_out() {
+    let seen = run(&config(), &[Signal::Silence(0.5), tone(0.1, 1.0), Signal::Silence(3.0)]);
+
+    // One second of tone plus the two seconds of silence that ended it
+    assert_eq!(
+        seen,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(1.0) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn quiet_input_never_triggers() {
+    let seen = run(
+        &config(),
+        &[
+            Signal::Silence(1.0),
+            // RMS about 0.006, under the silence threshold
+            Signal::Noise { amplitude: 0.01, seconds: 2.0 },
+            // RMS about 0.014, between the thresholds
+            tone(0.02, 2.0),
+        ],
+    );
+
+    assert_eq!(seen, vec![]);
+}
+
+#[test]
+fn noise_burst_is_recorded() {
+    let seen = run(
+        &config(),
+        &[Signal::Noise { amplitude: 0.2, seconds: 0.8 }, Signal::Silence(2.5)],
+    );
+
+    assert_eq!(
+        seen,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(0.8) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn short_click_is_cancelled() {
+    let seen = run(&config(), &[tone(0.9, 0.005), Signal::Silence(3.0)]);
+
+    // Started, but shorter than min_speech_duration, so never stopped
+    assert_eq!(seen, vec![Seen::Voice, Seen::Started, Seen::Silence]);
+}
+
+#[test]
+fn min_speech_duration_ignores_closing_silence() {
+    let config = AudioConfig {
+        min_speech_duration: 0.5,
+        ..config()
+    };
+
+    let too_short = run(&config, &[tone(0.1, 0.4), Signal::Silence(3.0)]);
+    assert_eq!(too_short, vec![Seen::Voice, Seen::Started, Seen::Silence]);
+
+    let long_enough = run(&config, &[tone(0.1, 0.5), Signal::Silence(3.0)]);
+    assert_eq!(
+        long_enough,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(0.5) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn pause_shorter_than_silence_duration_keeps_recording() {
+    let seen = run(
+        &config(),
+        &[
+            tone(0.1, 1.0),
+            Signal::Silence(1.5),
+            tone(0.1, 0.5),
+            Signal::Silence(3.0),
+        ],
+    );
+
+    // The second tone resets the silence timer, so both end up in one recording
+    assert_eq!(
+        seen,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Silence,
+            Seen::Stopped(samples(3.0) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn level_between_thresholds_sustains_recording() {
+    let seen = run(
+        &config(),
+        &[tone(0.1, 0.5), tone(0.02, 3.0), Signal::Silence(3.0)],
+    );
+
+    // Too quiet to start a recording, but not quiet enough to end one
+    assert_eq!(
+        seen,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(3.5) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn speech_envelope_is_one_recording() {
+    let seen = run(
+        &config(),
+        &[
+            Signal::Speech { amplitude: 0.3, seconds: 2.0 },
+            Signal::Silence(3.0),
+        ],
+    );
+
+    // Each trough between syllables is a brief silence, but never a long enough one
+    let stopped: Vec<&Seen> = seen
+        .iter()
+        .filter(|seen| matches!(seen, Seen::Stopped(_)))
+        .collect();
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(seen[..2], [Seen::Voice, Seen::Started]);
+    assert_eq!(seen.iter().filter(|seen| **seen == Seen::Silence).count(), 8);
+}
+
+#[test]
+fn consecutive_utterances_are_separate_recordings() {
+    let seen = run(
+        &config(),
+        &[
+            tone(0.1, 1.0),
+            Signal::Silence(2.5),
+            tone(0.1, 0.7),
+            Signal::Silence(2.5),
+        ],
+    );
+
+    assert_eq!(
+        seen,
+        vec![
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(1.0) + samples(2.0)),
+            Seen::Voice,
+            Seen::Started,
+            Seen::Silence,
+            Seen::Stopped(samples(0.7) + samples(2.0)),
+        ]
+    );
+}
+
+#[test]
+fn silence_timer_counts_samples_not_chunks() {
+    let mut vad = Vad::new(&config());
+    assert_eq!(vad.process(0.1, CHUNK), Some(VadEvent::SpeechStarted));
+
+    // Two seconds of silence in uneven chunks: 32000 = 1 + 31998 + 1 samples
+    assert_eq!(vad.process(0.0, 1), Some(VadEvent::SilenceStarted));
+    assert_eq!(vad.process(0.0, 31998), None);
+    assert!(vad.is_active());
+    assert_eq!(vad.process(0.0, 1), Some(VadEvent::TooShort));
+    assert!(!vad.is_active());
+}
+
+#[test]
+fn voice_resets_silence_timer() {
+    let mut vad = Vad::new(&config());
+    assert_eq!(vad.process(0.1, samples(1.0)), Some(VadEvent::SpeechStarted));
+    assert_eq!(vad.process(0.0, samples(1.9)), Some(VadEvent::SilenceStarted));
+    assert_eq!(vad.process(0.05, CHUNK), None);
+
+    // The timer starts over, so 1.9s of silence is again not enough
+    assert_eq!(vad.process(0.0, samples(1.0)), Some(VadEvent::SilenceStarted));
+    assert_eq!(vad.process(0.0, samples(0.9)), None);
+    assert_eq!(vad.process(0.0, samples(0.1)), Some(VadEvent::SpeechEnded));
+}
+
+#[test]
+fn voiced_ratio_excludes_closing_silence() {
+    let mut vad = Vad::new(&config());
+    vad.process(0.1, samples(1.0));
+    vad.process(0.0, samples(1.0));
+    vad.process(0.05, samples(1.0));
+    vad.process(0.0, samples(1.0));
+    assert_eq!(vad.process(0.0, samples(1.0)), Some(VadEvent::SpeechEnded));
+
+    // Two voiced seconds out of three before the closing silence
+    assert!((vad.voiced_ratio() - 2.0 / 3.0).abs() < 1e-6);
+}